@@ -1,10 +1,20 @@
 pub mod config {
     use macroquad::window::Conf;
 
+    /// Initial size of the window in pixels.
     pub const WINDOW_SIZE: i32 = 1000;
+    /// Size of the world in world units. The camera maps the world to the window.
+    pub const WORLD_SIZE: f32 = 1000.0;
     pub const FPS: u64 = 60;
+    /// Simulated seconds per tick. The simulation always advances in steps of `DT`,
+    /// independent of the rendering frame rate.
+    pub const DT: f32 = 1.0 / FPS as f32;
+    /// Most ticks simulated in one frame, so a slow frame can't make the simulation fall behind forever.
+    pub const MAX_TICKS_PER_FRAME: u32 = 240;
+    pub const MIN_TIME_SCALE: f32 = 0.25;
+    pub const MAX_TIME_SCALE: f32 = 16.0;
 
-    pub const SECTOR_WIDTH: f32 = WINDOW_SIZE as f32 / 12.0;
+    pub const SECTOR_WIDTH: f32 = WORLD_SIZE / 12.0;
 
     pub const CLOSE_CALL_DISTANCE: f32 = SECTOR_WIDTH * 0.9;
     pub const COLLISION_DISTANCE: f32 = SECTOR_WIDTH * 0.8;
@@ -16,13 +26,25 @@ pub mod config {
 
     pub const CRUISE_SPEED: f32 = SPEED_LIMIT * 0.35;
     pub const MARGIN: f32 = 4.0;
-    pub const RANDOM_INTERVAL: u64 = WINDOW_SIZE as u64 / SPEED_LIMIT as u64;
+    /// Default number of cars per second arriving on every approach while generating random cars.
+    pub const DEMAND_RATE: f32 = 0.5;
+    /// Default number of ticks a car can stand still before cars from other directions give
+    /// way to it.
+    pub const MAX_WAIT: u64 = 10 * FPS;
+    /// Seconds a car in a platoon keeps between itself and the car in front of it, on top of
+    /// `PLATOON_SPACING`.
+    pub const PLATOON_GAP: f32 = 0.3;
+    /// Distance between the centers of two cars of a platoon standing still.
+    pub const PLATOON_SPACING: f32 = SECTOR_WIDTH * 1.2;
+    /// Furthest a car can be behind the car in front of it in its lane to join its platoon.
+    pub const PLATOON_DISTANCE: f32 = SCAN_DISTANCE + SECTOR_WIDTH;
+    pub const MAX_PLATOON_SIZE: usize = 4;
     pub fn window_conf() -> Conf {
         Conf {
             window_title: "Smart-Road | Grit:lab".to_owned(),
             window_width: WINDOW_SIZE,
             window_height: WINDOW_SIZE,
-            window_resizable: false,
+            window_resizable: true,
             ..Default::default()
         }
     }
@@ -31,9 +53,18 @@ pub mod config {
 pub mod controls {
     use macroquad::prelude::*;
 
-    use crate::traffic::{Direction, State};
+    use crate::render::camera::Camera;
+    use crate::render::timeline::{timeline_bar, timeline_time};
+    use crate::traffic::replay::Replay;
+    use crate::traffic::{Clock, Direction, Path, State, Turning};
 
-    pub fn handle_input(state: &mut State) {
+    /// Factor the zoom changes by per step of the mouse wheel.
+    const ZOOM_STEP: f32 = 1.1;
+
+    /// Seconds the arrow keys seek by in a replay.
+    const SEEK_STEP: f32 = 5.0;
+
+    pub fn handle_input(state: &mut State, clock: &mut Clock, camera: &mut Camera) {
         if is_key_pressed(KeyCode::Escape) && !state.show_final_statistics {
             state.show_final_statistics = true;
         } else if is_key_pressed(KeyCode::Escape) && state.show_final_statistics {
@@ -41,44 +72,198 @@ pub mod controls {
         }
 
         if is_key_pressed(KeyCode::Up) {
-            state.add_car(Direction::South);
-            state.random = false;
+            spawn(state, Direction::South);
         }
 
         if is_key_pressed(KeyCode::Down) {
-            state.add_car(Direction::North);
-            state.random = false;
+            spawn(state, Direction::North);
         }
 
         if is_key_pressed(KeyCode::Right) {
-            state.add_car(Direction::West);
-            state.random = false;
+            spawn(state, Direction::West);
         }
 
         if is_key_pressed(KeyCode::Left) {
-            state.add_car(Direction::East);
-            state.random = false;
+            spawn(state, Direction::East);
         }
 
         if is_key_pressed(KeyCode::R) {
             state.random = !state.random;
         }
+
+        if is_key_pressed(KeyCode::P) {
+            state.platooning = !state.platooning;
+        }
+
+        if is_key_pressed(KeyCode::H) {
+            state.show_hud = !state.show_hud;
+        }
+
+        if is_key_pressed(KeyCode::D) {
+            state.show_debug = !state.show_debug;
+        }
+
+        if is_key_pressed(KeyCode::T) {
+            state.textured_roads = !state.textured_roads;
+        }
+
+        if is_mouse_button_pressed(MouseButton::Left) {
+            let Vec2 { x, y } = camera.screen_to_world(mouse_position().into());
+            state.inspected = state.car_at(x, y).map(|car| car.id);
+
+            // Clicking an empty approach lane spawns a car into that lane. A busy lane is
+            // counted in the statistics as a rejected spawn.
+            if let (None, Some((direction, turning))) = (state.inspected, Path::approach_at(x, y)) {
+                let _ = state.add_car_turning(direction, turning);
+                state.random = false;
+            }
+        }
+
+        if is_key_pressed(KeyCode::Space) {
+            clock.toggle_pause();
+        }
+
+        if is_key_pressed(KeyCode::Period) {
+            clock.step();
+        }
+
+        if is_key_pressed(KeyCode::Equal) || is_key_pressed(KeyCode::KpAdd) {
+            clock.speed_up();
+        }
+
+        if is_key_pressed(KeyCode::Minus) || is_key_pressed(KeyCode::KpSubtract) {
+            clock.slow_down();
+        }
+
+        handle_camera(camera);
+    }
+
+    /// ### handle_replay_input
+    /// Controls while playing back a replay. Clicking or dragging on the timeline seeks to
+    /// that time.
+    pub fn handle_replay_input(replay: &mut Replay, clock: &mut Clock, camera: &mut Camera) {
+        if is_key_pressed(KeyCode::Escape) {
+            std::process::exit(0);
+        }
+
+        if is_key_pressed(KeyCode::Space) {
+            clock.toggle_pause();
+        }
+
+        if is_key_pressed(KeyCode::Period) {
+            clock.step();
+        }
+
+        if is_key_pressed(KeyCode::Equal) || is_key_pressed(KeyCode::KpAdd) {
+            clock.speed_up();
+        }
+
+        if is_key_pressed(KeyCode::Minus) || is_key_pressed(KeyCode::KpSubtract) {
+            clock.slow_down();
+        }
+
+        if is_key_pressed(KeyCode::B) {
+            replay.reverse = !replay.reverse;
+        }
+
+        if is_key_pressed(KeyCode::N) {
+            replay.next_incident();
+        }
+
+        if is_key_pressed(KeyCode::Left) {
+            replay.seek(replay.time() - SEEK_STEP);
+        }
+
+        if is_key_pressed(KeyCode::Right) {
+            replay.seek(replay.time() + SEEK_STEP);
+        }
+
+        if is_key_pressed(KeyCode::Home) {
+            replay.seek(0.0);
+        }
+
+        if is_key_pressed(KeyCode::End) {
+            replay.seek(replay.duration());
+        }
+
+        let (x, y) = mouse_position();
+        let mut bar = timeline_bar();
+        bar.y -= bar.h;
+        bar.h *= 3.0;
+        if is_mouse_button_down(MouseButton::Left) && bar.contains(vec2(x, y)) {
+            replay.seek(timeline_time(replay, x));
+        }
+
+        handle_camera(camera);
+    }
+
+    // Zoom with the mouse wheel, pan by dragging with the right mouse button.
+    fn handle_camera(camera: &mut Camera) {
+        let mouse = Vec2::from(mouse_position());
+
+        let (_, wheel) = mouse_wheel();
+        if wheel > 0.0 {
+            camera.zoom_at(mouse, ZOOM_STEP);
+        } else if wheel < 0.0 {
+            camera.zoom_at(mouse, 1.0 / ZOOM_STEP);
+        }
+
+        if is_mouse_button_down(MouseButton::Right) {
+            camera.drag(mouse);
+        } else {
+            camera.release();
+        }
+
+        if is_key_pressed(KeyCode::C) {
+            *camera = Camera::new();
+        }
+    }
+
+    // Spawn a car from `direction`, in the lane chosen by the held modifier key or a random one.
+    // A busy lane is counted in the statistics as a rejected spawn.
+    fn spawn(state: &mut State, direction: Direction) {
+        let _ = match turning_modifier() {
+            Some(turning) => state.add_car_turning(direction, turning),
+            None => state.add_car(direction),
+        };
+        state.random = false;
+    }
+
+    fn turning_modifier() -> Option<Turning> {
+        if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) {
+            Some(Turning::Left)
+        } else if is_key_down(KeyCode::LeftAlt) || is_key_down(KeyCode::RightAlt) {
+            Some(Turning::Straight)
+        } else if is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl) {
+            Some(Turning::Right)
+        } else {
+            None
+        }
     }
 }
 
 pub mod traffic {
     pub use car::*;
-    pub use collision::*;
-    pub use path::*;
+    pub use clock::Clock;
     pub use path::*;
-    pub use state::{Direction, State};
+    pub use perception::Perceived;
+    pub use spatial::SpatialIndex;
+    pub use state::{Direction, SpawnError, State};
     pub use statistics::*;
 
     pub mod car;
+    pub mod clock;
+    pub mod demand;
     pub mod path;
+    pub mod perception;
+    pub mod replay;
     pub mod road;
+    pub mod scenario;
+    pub mod spatial;
     pub mod state;
     pub mod statistics;
+    pub mod v2v;
+    pub mod watchdog;
 
     pub mod collision;
 }
@@ -87,13 +272,25 @@ pub mod render {
     pub const FONT_SIZE: f32 = 20.0;
     pub const TITLE_SIZE: f32 = FONT_SIZE * 1.5;
 
-    pub use roads::render_textured_roads;
+    pub use camera::Camera;
+    pub use clock::render_clock;
+    pub use debug::render_debug;
+    pub use hud::render_hud;
+    pub use inspector::render_inspector;
+    pub use roads::{render_roads, render_textured_roads};
     pub use textures::Textures;
+    pub use timeline::render_timeline;
 
     pub mod roads;
 
+    pub mod camera;
     pub mod car;
+    pub mod clock;
+    pub mod debug;
+    pub mod hud;
+    pub mod inspector;
     pub mod textures;
+    pub mod timeline;
 
     pub use car::render_car;
 
@@ -1,49 +1,150 @@
-use std::thread;
-use std::time::{Duration, Instant};
+use std::path::PathBuf;
 
 use macroquad::prelude::*;
 
-use smart_road::config::{window_conf, FPS, RANDOM_INTERVAL};
-use smart_road::controls::handle_input;
+use smart_road::config::window_conf;
+use smart_road::controls::{handle_input, handle_replay_input};
+use smart_road::render::camera::Camera;
 use smart_road::render::car::render_car;
-use smart_road::render::roads::render_textured_roads;
+use smart_road::render::clock::render_clock;
+use smart_road::render::debug::render_debug;
+use smart_road::render::hud::render_hud;
+use smart_road::render::inspector::render_inspector;
+use smart_road::render::roads::{render_roads, render_textured_roads};
 use smart_road::render::statistics::render_statistics;
+use smart_road::render::textures::Textures;
+use smart_road::render::timeline::render_timeline;
+use smart_road::traffic::demand::{Demand, DemandGenerator, OdMatrix, Profile};
+use smart_road::traffic::perception::Perception;
+use smart_road::traffic::replay::{Recorder, Replay};
+use smart_road::traffic::scenario::{Scenario, ScenarioRunner};
+use smart_road::traffic::v2v::{Channel, MessageBus};
 use smart_road::traffic::*;
 
 #[macroquad::main(window_conf)]
 async fn main() {
-    let textures = smart_road::render::textures::Textures::load().await;
-    let mut state = State::new();
+    let (textures, errors) = Textures::load(arg_path("--assets").as_deref());
+    for err in errors {
+        eprintln!("Failed to load sprite, drawing a simple shape instead: {err}");
+    }
+    if let Some(path) = arg_path("--replay") {
+        match Replay::load(&path) {
+            Ok(replay) => play(replay, &textures).await,
+            Err(err) => eprintln!("Failed to load replay {}: {err}", path.display()),
+        }
+        return;
+    }
 
-    let frame_duration = Duration::from_micros(1_000_000 / FPS);
-    let mut last_frame_time = Instant::now();
+    let mut state = State::new();
+    let mut clock = Clock::new();
+    let mut camera = Camera::new();
+    let mut recorder = arg_path("--record").and_then(|path| match Recorder::create(&path) {
+        Ok(recorder) => Some(recorder),
+        Err(err) => {
+            eprintln!("Failed to create replay {}: {err}", path.display());
+            None
+        }
+    });
+    if recorder.is_some() {
+        state.start_recording();
+    }
+    let mut scenario = arg_path("--scenario").and_then(|path| match Scenario::load(&path) {
+        Ok(scenario) => Some(ScenarioRunner::new(scenario)),
+        Err(err) => {
+            eprintln!("Failed to load scenario {}: {err}", path.display());
+            None
+        }
+    });
+    if let Some(runner) = &scenario {
+        state.platooning = runner.scenario().platooning;
+        state.bus = MessageBus::new(runner.scenario().channel);
+        state.perception = runner.scenario().perception;
+    }
 
-    let mut random_timer = Instant::now();
-    let random_interval = Duration::from_millis(RANDOM_INTERVAL);
+    if let Some(path) = arg_path("--demand") {
+        match Demand::load(&path) {
+            Ok(demand) => state.demand = DemandGenerator::new(demand),
+            Err(err) => eprintln!("Failed to load demand {}: {err}", path.display()),
+        }
+    }
+    if let Some(path) = arg_path("--od") {
+        match OdMatrix::load(&path) {
+            Ok(od) => {
+                let demand = state.demand.demand();
+                let (seed, profile) = (demand.seed, demand.profile.clone());
+                state.demand = DemandGenerator::new(Demand {
+                    seed,
+                    profile,
+                    ..Demand::from_od(&od)
+                });
+            }
+            Err(err) => eprintln!("Failed to load OD matrix {}: {err}", path.display()),
+        }
+    }
+    if let Some(path) = arg_path("--profile") {
+        match Profile::load(&path) {
+            Ok(profile) => {
+                let mut demand = state.demand.demand().clone();
+                demand.profile = Some(profile);
+                state.demand = DemandGenerator::new(demand);
+            }
+            Err(err) => eprintln!("Failed to load demand profile {}: {err}", path.display()),
+        }
+    }
+    if let Some(path) = arg_path("--channel") {
+        match Channel::load(&path) {
+            Ok(channel) => state.bus = MessageBus::new(channel),
+            Err(err) => eprintln!("Failed to load channel {}: {err}", path.display()),
+        }
+    }
+    if let Some(path) = arg_path("--perception") {
+        match Perception::load(&path) {
+            Ok(perception) => state.perception = perception,
+            Err(err) => eprintln!("Failed to load perception {}: {err}", path.display()),
+        }
+    }
 
     loop {
         clear_background(BLACK);
-        handle_input(&mut state);
+        handle_input(&mut state, &mut clock, &mut camera);
         if !state.show_final_statistics {
-            render_textured_roads(&textures);
+            // The world is drawn through the camera, overlays in screen space
+            set_camera(&camera.camera2d());
+            if state.textured_roads {
+                render_textured_roads(&textures);
+            } else {
+                render_roads();
+            }
 
-            if state.random && random_timer.elapsed() > random_interval {
-                state.add_car_random();
-                random_timer = Instant::now();
+            for _ in 0..clock.advance(get_frame_time()) {
+                match &mut scenario {
+                    Some(runner) => runner.update(&mut state),
+                    None => state.update(),
+                }
+            }
+            if let Some(rec) = &mut recorder {
+                if let Err(err) = rec.write(&mut state) {
+                    eprintln!("Failed to write replay, recording stopped: {err}");
+                    state.stop_recording();
+                    recorder = None;
+                }
             }
-            state.update();
 
             for road in &state.roads {
                 for car in road.cars.iter().flatten() {
                     render_car(car, &textures.cars);
                 }
             }
-            let elapsed = last_frame_time.elapsed();
-            if elapsed < frame_duration {
-                thread::sleep(frame_duration - elapsed);
+            if state.show_debug {
+                render_debug(&state);
             }
 
-            last_frame_time = Instant::now();
+            set_default_camera();
+            render_inspector(&state, &camera);
+            render_clock(&clock, state.time());
+            if state.show_hud {
+                render_hud(&state);
+            }
         } else {
             state.stats.set_max_vehicles(state.total_cars);
             render_statistics(&state.stats);
@@ -51,3 +152,41 @@ async fn main() {
         next_frame().await
     }
 }
+
+// Play back a recorded run, drawing the recorded cars without simulating them
+async fn play(mut replay: Replay, textures: &Textures) {
+    let mut clock = Clock::new();
+    let mut camera = Camera::new();
+
+    loop {
+        clear_background(BLACK);
+        handle_replay_input(&mut replay, &mut clock, &mut camera);
+
+        set_camera(&camera.camera2d());
+        render_roads();
+        replay.advance(clock.advance(get_frame_time()));
+        for car in replay.cars() {
+            render_car(&car, &textures.cars);
+        }
+
+        set_default_camera();
+        render_timeline(&replay, &clock);
+        next_frame().await
+    }
+}
+
+// Path given with `--assets <dir>` to override the embedded sprites,
+// with `--record <file>` to record a replay of the run, with `--replay <file>` to play one,
+// with `--scenario <file>` to spawn the cars of a scenario, with `--demand <file>` or
+// `--od <file>` to set the arrivals generated with `R`, with `--profile <file>` to let their
+// rates change over time, with `--channel <file>` to delay and lose the messages between cars
+// or with `--perception <file>` to limit and add noise to what the cars see
+fn arg_path(flag: &str) -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next().map(PathBuf::from);
+        }
+    }
+    None
+}
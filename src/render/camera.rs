@@ -0,0 +1,96 @@
+use macroquad::prelude::*;
+
+use crate::config::WORLD_SIZE;
+
+pub const MIN_ZOOM: f32 = 0.25;
+pub const MAX_ZOOM: f32 = 8.0;
+
+/// ### Camera
+/// Maps world coordinates to screen pixels. At a `zoom` of 1.0 the whole world fits the
+/// shortest side of the window, centered on `target`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    /// World position in the center of the screen.
+    pub target: Vec2,
+    pub zoom: f32,
+    drag_from: Option<Vec2>,
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        Camera {
+            target: vec2(WORLD_SIZE / 2.0, WORLD_SIZE / 2.0),
+            zoom: 1.0,
+            drag_from: None,
+        }
+    }
+
+    /// ### camera2d
+    /// The macroquad camera to draw the world with.
+    pub fn camera2d(&self) -> Camera2D {
+        let screen = screen_size();
+        let scale = self.scale(screen);
+        Camera2D {
+            target: self.target,
+            zoom: vec2(2.0 * scale / screen.x, -2.0 * scale / screen.y),
+            ..Default::default()
+        }
+    }
+
+    pub fn world_to_screen(&self, point: Vec2) -> Vec2 {
+        self.to_screen(point, screen_size())
+    }
+
+    pub fn screen_to_world(&self, point: Vec2) -> Vec2 {
+        self.to_world(point, screen_size())
+    }
+
+    /// ### to_screen
+    /// Map a world position to pixels on a screen of size `screen`.
+    pub fn to_screen(&self, point: Vec2, screen: Vec2) -> Vec2 {
+        (point - self.target) * self.scale(screen) + screen / 2.0
+    }
+
+    /// ### to_world
+    /// Map pixels on a screen of size `screen` to a world position.
+    pub fn to_world(&self, point: Vec2, screen: Vec2) -> Vec2 {
+        (point - screen / 2.0) / self.scale(screen) + self.target
+    }
+
+    /// ### zoom_at
+    /// Zoom by `factor`, keeping the world position under the screen point `point` in place.
+    pub fn zoom_at(&mut self, point: Vec2, factor: f32) {
+        let screen = screen_size();
+        let anchor = self.to_world(point, screen);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.target = anchor - (point - screen / 2.0) / self.scale(screen);
+    }
+
+    /// ### drag
+    /// Pan the camera so the world follows the mouse at `point` since the last drag.
+    pub fn drag(&mut self, point: Vec2) {
+        if let Some(from) = self.drag_from {
+            self.target -= (point - from) / self.scale(screen_size());
+        }
+        self.drag_from = Some(point);
+    }
+
+    pub fn release(&mut self) {
+        self.drag_from = None;
+    }
+
+    // Pixels per world unit
+    fn scale(&self, screen: Vec2) -> f32 {
+        screen.x.min(screen.y) / WORLD_SIZE * self.zoom
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn screen_size() -> Vec2 {
+    vec2(screen_width(), screen_height())
+}
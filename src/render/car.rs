@@ -4,11 +4,11 @@ use crate::{
     traffic::{car::Car, Moving},
 };
 use macroquad::prelude::*;
-pub fn render_car(car: &Car, textures: &[Texture2D]) {
-    let texture = match car.model {
-        Model::Standard => &textures[0],
-        Model::Audi => &textures[1],
-        Model::Viper => &textures[2],
+pub fn render_car(car: &Car, textures: &[Option<Texture2D>]) {
+    let (texture, color) = match car.model {
+        Model::Standard => (&textures[0], BLUE),
+        Model::Audi => (&textures[1], LIGHTGRAY),
+        Model::Viper => (&textures[2], RED),
     };
     // Determine which sprite to use based on the car's direction
     let rotation: f32 = match car.moving {
@@ -24,6 +24,11 @@ pub fn render_car(car: &Car, textures: &[Texture2D]) {
     let center_x = car.x + (SECTOR_WIDTH - scaled_size) / 2.0;
     let center_y = car.y + (SECTOR_WIDTH - scaled_size) / 2.0;
 
+    let Some(texture) = texture else {
+        render_shape(car, center_x, center_y, scaled_size, color);
+        return;
+    };
+
     draw_texture_ex(
         texture,
         center_x,
@@ -37,3 +42,37 @@ pub fn render_car(car: &Car, textures: &[Texture2D]) {
         },
     );
 }
+
+// Draw the car as a coloured rectangle with a dark windshield at the front,
+// for when its sprite is missing.
+fn render_shape(car: &Car, x: f32, y: f32, size: f32, color: Color) {
+    let width = size * 0.6;
+    let inset = (size - width) / 2.0;
+    let shield = size * 0.2;
+    let (body, windshield) = match car.moving {
+        Moving::Up => (
+            Rect::new(x + inset, y, width, size),
+            Rect::new(x + inset, y + shield, width, shield),
+        ),
+        Moving::Down => (
+            Rect::new(x + inset, y, width, size),
+            Rect::new(x + inset, y + size - 2.0 * shield, width, shield),
+        ),
+        Moving::Left => (
+            Rect::new(x, y + inset, size, width),
+            Rect::new(x + shield, y + inset, shield, width),
+        ),
+        Moving::Right => (
+            Rect::new(x, y + inset, size, width),
+            Rect::new(x + size - 2.0 * shield, y + inset, shield, width),
+        ),
+    };
+    draw_rectangle(body.x, body.y, body.w, body.h, color);
+    draw_rectangle(
+        windshield.x,
+        windshield.y,
+        windshield.w,
+        windshield.h,
+        DARKGRAY,
+    );
+}
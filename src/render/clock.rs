@@ -0,0 +1,25 @@
+use macroquad::prelude::*;
+
+use crate::render::statistics::round_to_tenth;
+use crate::render::FONT_SIZE;
+use crate::traffic::Clock;
+
+/// ### render_clock
+/// Show whether the simulation is paused or running at what speed, and the simulated time.
+pub fn render_clock(clock: &Clock, time: f32) {
+    let mode = if clock.paused {
+        "PAUSED".to_string()
+    } else {
+        format!("{}x", clock.time_scale)
+    };
+    let text = format!("{mode} | {} s", round_to_tenth(time));
+
+    draw_rectangle(
+        0.0,
+        0.0,
+        180.0,
+        FONT_SIZE + 10.0,
+        Color::new(0.0, 0.0, 0.0, 0.6),
+    );
+    draw_text(&text, 10.0, FONT_SIZE, FONT_SIZE, WHITE);
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use macroquad::prelude::*;
+
+use crate::config::{ACCELERATION_DISTANCE, MARGIN, SCAN_DISTANCE, SECTOR_WIDTH, WORLD_SIZE};
+use crate::traffic::spatial::GRID_SIZE;
+use crate::traffic::{Car, Moving, State};
+
+const GRID_COLOR: Color = Color::new(1.0, 1.0, 1.0, 0.2);
+const PATH_COLOR: Color = Color::new(0.0, 0.6, 1.0, 0.15);
+const SCAN_COLOR: Color = Color::new(1.0, 1.0, 0.0, 0.4);
+const ACCELERATION_COLOR: Color = Color::new(0.0, 1.0, 0.0, 0.4);
+const BAND_COLOR: Color = Color::new(1.0, 0.5, 0.0, 0.2);
+
+/// ### render_debug
+/// Draw the grid of sectors and what each car sees: its remaining `Path`, its scan radii,
+/// the band scanned by `forward_scan` and a line to the car it is braking for.
+pub fn render_debug(state: &State) {
+    render_grid();
+
+    let cars: Vec<&Car> = state
+        .roads
+        .iter()
+        .flat_map(|road| road.cars.iter().flatten())
+        .collect();
+    let centers: HashMap<usize, (f32, f32)> =
+        cars.iter().map(|car| (car.id, car.center_car())).collect();
+
+    for car in &cars {
+        render_path(car);
+    }
+
+    for car in &cars {
+        let (x, y) = car.center_car();
+        draw_circle_lines(x, y, SCAN_DISTANCE, 1.0, SCAN_COLOR);
+        draw_circle_lines(x, y, ACCELERATION_DISTANCE, 1.0, ACCELERATION_COLOR);
+        render_forward_band(car);
+
+        if let Some(&(x2, y2)) = car.braking_for.and_then(|id| centers.get(&id)) {
+            draw_line(x, y, x2, y2, 3.0, RED);
+            draw_circle(x2, y2, 6.0, RED);
+        }
+    }
+}
+
+fn render_grid() {
+    for i in 0..=GRID_SIZE {
+        let pos = i as f32 * SECTOR_WIDTH;
+        draw_line(pos, 0.0, pos, WORLD_SIZE, 1.0, GRID_COLOR);
+        draw_line(0.0, pos, WORLD_SIZE, pos, 1.0, GRID_COLOR);
+    }
+}
+
+fn render_path(car: &Car) {
+    for sector in &car.path.sectors[car.index..] {
+        draw_rectangle(
+            sector.get_x() as f32 * SECTOR_WIDTH,
+            sector.get_y() as f32 * SECTOR_WIDTH,
+            SECTOR_WIDTH,
+            SECTOR_WIDTH,
+            PATH_COLOR,
+        );
+    }
+}
+
+// The band in front of the car that `forward_scan` checks, up to the `SCAN_DISTANCE`
+fn render_forward_band(car: &Car) {
+    let borders = car.borders();
+    let (x, y) = car.center_car();
+    let width = SECTOR_WIDTH - 2.0 * MARGIN;
+    let (x, y, w, h) = match car.moving {
+        Moving::Up => (
+            borders.left + MARGIN,
+            y - SCAN_DISTANCE,
+            width,
+            SCAN_DISTANCE,
+        ),
+        Moving::Down => (borders.left + MARGIN, y, width, SCAN_DISTANCE),
+        Moving::Right => (x, borders.top + MARGIN, SCAN_DISTANCE, width),
+        Moving::Left => (
+            x - SCAN_DISTANCE,
+            borders.top + MARGIN,
+            SCAN_DISTANCE,
+            width,
+        ),
+    };
+    draw_rectangle(x, y, w, h, BAND_COLOR);
+}
@@ -0,0 +1,70 @@
+use macroquad::prelude::*;
+
+use crate::render::statistics::round_to_tenth;
+use crate::render::FONT_SIZE;
+use crate::traffic::statistics::THROUGHPUT_WINDOW;
+use crate::traffic::State;
+
+const HUD_WIDTH: f32 = 260.0;
+
+/// ### render_hud
+/// Overlay the running statistics on top of the live intersection.
+pub fn render_hud(state: &State) {
+    let hud_x_pos = screen_width() - HUD_WIDTH;
+    let stats = &state.stats;
+    let [north, east, south, west] = state.cars_per_approach();
+    let waiting = state.waiting_per_approach().map(round_to_tenth);
+    let queued: usize = state.queued_per_approach().iter().sum();
+    let lines = [
+        format!("Cars: {}", state.car_count()),
+        format!("N: {north}  E: {east}  S: {south}  W: {west}"),
+        format!(
+            "Throughput: {} cars / {} s",
+            stats.throughput(state.time()),
+            THROUGHPUT_WINDOW
+        ),
+        format!("Mean Time: {} s", round_to_tenth(stats.mean_time())),
+        format!("Close Calls: {}", stats.close_calls()),
+        format!("Collisions: {}", stats.collisions()),
+        format!("Deadlocks: {}", stats.deadlocks().len()),
+        format!("Queued: {queued}  Rejected: {}", stats.rejected()),
+        format!("Max Wait: {} s", round_to_tenth(stats.max_wait())),
+        format!(
+            "Waiting: N {}  E {}  S {}  W {}",
+            waiting[0], waiting[1], waiting[2], waiting[3]
+        ),
+        match state.platooning {
+            true => format!(
+                "Platoons: {}  Size: {}  Gap: {} s",
+                state.platoon_count(),
+                round_to_tenth(stats.mean_platoon_size()),
+                round_to_tenth(stats.mean_platoon_gap())
+            ),
+            false => "Platoons: off".to_string(),
+        },
+        format!(
+            "Messages: {}% lost  Age: {} s",
+            round_to_tenth(stats.message_loss() * 100.0),
+            round_to_tenth(stats.mean_message_age())
+        ),
+        format!("Missed Detections: {}", stats.missed()),
+    ];
+
+    // Render a translucent rectangle as a backdrop
+    draw_rectangle(
+        hud_x_pos,
+        0.0,
+        HUD_WIDTH,
+        (lines.len() as f32 + 0.5) * FONT_SIZE,
+        Color::new(0.0, 0.0, 0.0, 0.6),
+    );
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            line,
+            hud_x_pos + 10.0,
+            (i + 1) as f32 * FONT_SIZE,
+            FONT_SIZE,
+            WHITE,
+        );
+    }
+}
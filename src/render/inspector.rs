@@ -0,0 +1,71 @@
+use macroquad::prelude::*;
+
+use crate::config::DT;
+use crate::render::camera::Camera;
+use crate::render::statistics::round_to_tenth;
+use crate::render::FONT_SIZE;
+use crate::traffic::State;
+
+const PANEL_WIDTH: f32 = 300.0;
+
+/// ### render_inspector
+/// Show the details of the inspected car, and outline it in the intersection.
+/// Drawn in screen space, the `camera` is used to find the car on the screen.
+pub fn render_inspector(state: &State, camera: &Camera) {
+    let Some(car) = state.inspected.and_then(|id| state.car(id)) else {
+        return;
+    };
+
+    let borders = car.borders();
+    let top_left = camera.world_to_screen(vec2(borders.left, borders.top));
+    let bottom_right = camera.world_to_screen(vec2(borders.right, borders.bottom));
+    let size = bottom_right - top_left;
+    draw_rectangle_lines(top_left.x, top_left.y, size.x, size.y, 3.0, YELLOW);
+
+    let braking = match (car.brake_reason, car.braking_for) {
+        (Some(reason), Some(id)) => format!("{reason:?} (car {id})"),
+        (Some(reason), None) => format!("{reason:?}"),
+        _ => "-".to_string(),
+    };
+    let lines = [
+        format!("Car {}", car.id),
+        format!("Direction: {:?}", car.direction),
+        format!("Turning: {:?}", car.turning),
+        format!("Model: {:?}", car.model),
+        format!("Index: {} / {}", car.index, car.path.sectors.len()),
+        format!("Sector Position: {}", round_to_tenth(car.sector_pos())),
+        format!("Velocity: {}", round_to_tenth(car.vel)),
+        format!("Time: {} s", round_to_tenth(car.time_in_system(state.tick))),
+        format!("Braking: {braking}"),
+        format!(
+            "Waited: {} s{}",
+            round_to_tenth(car.waited as f32 * DT),
+            if car.priority { " (priority)" } else { "" }
+        ),
+        match (car.platoon, car.ahead) {
+            (Some(leader), Some(ahead)) => format!("Platoon: car {leader}, following car {ahead}"),
+            (Some(_), None) => "Platoon: leading".to_string(),
+            _ => "Platoon: -".to_string(),
+        },
+    ];
+
+    let panel_y_pos = screen_height() - (lines.len() as f32 + 1.0) * FONT_SIZE;
+
+    // Render a translucent rectangle as a backdrop
+    draw_rectangle(
+        0.0,
+        panel_y_pos,
+        PANEL_WIDTH,
+        (lines.len() as f32 + 0.5) * FONT_SIZE,
+        Color::new(0.0, 0.0, 0.0, 0.6),
+    );
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            line,
+            10.0,
+            panel_y_pos + (i + 1) as f32 * FONT_SIZE,
+            FONT_SIZE,
+            WHITE,
+        );
+    }
+}
@@ -1,7 +1,194 @@
+use std::collections::HashMap;
+
+use crate::config::{SECTOR_WIDTH, WORLD_SIZE};
 use crate::render::textures::Textures;
+use crate::traffic::{Direction, Moving, Path, Sector, Turning, APPROACH_LENGTH};
 
 use macroquad::prelude::*;
 
+const GRASS: Color = Color::new(0.25, 0.45, 0.2, 1.0);
+const ASPHALT: Color = Color::new(0.25, 0.25, 0.27, 1.0);
+const MARKING: Color = Color::new(0.9, 0.9, 0.9, 1.0);
+const CENTER_LINE: Color = Color::new(0.95, 0.8, 0.2, 1.0);
+const LINE_WIDTH: f32 = 3.0;
+const DASH_LENGTH: f32 = SECTOR_WIDTH / 4.0;
+
+/// ### render_textured_roads
+/// Draw the background texture, or the procedural roads if it is missing.
 pub fn render_textured_roads(textures: &Textures) {
-    draw_texture(&textures.bg, 0.0, 0.0, WHITE);
+    let Some(bg) = &textures.bg else {
+        render_roads();
+        return;
+    };
+    draw_texture_ex(
+        bg,
+        0.0,
+        0.0,
+        WHITE,
+        DrawTextureParams {
+            dest_size: Some(Vec2::new(WORLD_SIZE, WORLD_SIZE)),
+            ..Default::default()
+        },
+    );
+}
+
+/// ### render_roads
+/// Draw the roads from the `Path`s through the intersection: asphalt on every sector a path
+/// uses, lane markings between the sectors outside of the intersection, a stop line at the
+/// end of every approach lane and an arrow showing the turning of the lane.
+pub fn render_roads() {
+    draw_rectangle(0.0, 0.0, WORLD_SIZE, WORLD_SIZE, GRASS);
+
+    // The direction of traffic in every sector a path uses
+    let mut roads: HashMap<(usize, usize), Moving> = HashMap::new();
+    for path in Path::all() {
+        for sector in &path.sectors {
+            roads.insert((sector.get_x(), sector.get_y()), sector.moving);
+        }
+    }
+    let (min, max) = intersection_bounds();
+    let inside =
+        |(x, y): (usize, usize)| (min.0..=max.0).contains(&x) && (min.1..=max.1).contains(&y);
+
+    for &(x, y) in roads.keys() {
+        let (left, top) = (x as f32 * SECTOR_WIDTH, y as f32 * SECTOR_WIDTH);
+        draw_rectangle(left, top, SECTOR_WIDTH, SECTOR_WIDTH, ASPHALT);
+    }
+
+    // Lane markings on the right and bottom edge of every sector outside the intersection
+    for (&(x, y), moving) in &roads {
+        if inside((x, y)) {
+            continue;
+        }
+        let (left, top) = (x as f32 * SECTOR_WIDTH, y as f32 * SECTOR_WIDTH);
+        let neighbours = [
+            ((x + 1, y), vec2(left + SECTOR_WIDTH, top), vec2(0.0, 1.0)),
+            ((x, y + 1), vec2(left, top + SECTOR_WIDTH), vec2(1.0, 0.0)),
+        ];
+        for (neighbour, from, along) in neighbours {
+            let Some(other) = roads.get(&neighbour).filter(|_| !inside(neighbour)) else {
+                continue;
+            };
+            if other == moving {
+                draw_dashed_line(from, along, MARKING);
+            } else if is_opposite(moving, other) {
+                draw_center_line(from, along);
+            }
+        }
+    }
+
+    for direction in Direction::ALL {
+        for turning in Turning::ALL {
+            let path = Path::get(&direction, &turning);
+            render_stop_line(&path.sectors[APPROACH_LENGTH - 1]);
+            render_arrow(&path.sectors[1], &turning);
+        }
+    }
+}
+
+/// ### intersection_bounds
+/// The smallest box of sectors containing every sector where a path changes direction.
+fn intersection_bounds() -> ((usize, usize), (usize, usize)) {
+    let mut min = (usize::MAX, usize::MAX);
+    let mut max = (0, 0);
+    for path in Path::all() {
+        for pair in path.sectors.windows(2) {
+            if pair[0].moving != pair[1].moving {
+                let (x, y) = (pair[0].get_x(), pair[0].get_y());
+                min = (min.0.min(x), min.1.min(y));
+                max = (max.0.max(x), max.1.max(y));
+            }
+        }
+    }
+    (min, max)
+}
+
+fn is_opposite(a: &Moving, b: &Moving) -> bool {
+    matches!(
+        (a, b),
+        (Moving::Up, Moving::Down)
+            | (Moving::Down, Moving::Up)
+            | (Moving::Left, Moving::Right)
+            | (Moving::Right, Moving::Left)
+    )
+}
+
+fn draw_dashed_line(from: Vec2, along: Vec2, color: Color) {
+    let mut pos = 0.0;
+    while pos < SECTOR_WIDTH {
+        let start = from + along * pos;
+        let end = from + along * (pos + DASH_LENGTH).min(SECTOR_WIDTH);
+        draw_line(start.x, start.y, end.x, end.y, LINE_WIDTH, color);
+        pos += DASH_LENGTH * 2.0;
+    }
+}
+
+fn draw_center_line(from: Vec2, along: Vec2) {
+    let across = vec2(along.y, along.x) * LINE_WIDTH;
+    for offset in [across, -across] {
+        let (start, end) = (from + offset, from + offset + along * SECTOR_WIDTH);
+        draw_line(start.x, start.y, end.x, end.y, LINE_WIDTH, CENTER_LINE);
+    }
+}
+
+// The stop line is on the edge of the last approach sector, facing the intersection
+fn render_stop_line(sector: &Sector) {
+    let (left, top) = (
+        sector.get_x() as f32 * SECTOR_WIDTH,
+        sector.get_y() as f32 * SECTOR_WIDTH,
+    );
+    let (right, bottom) = (left + SECTOR_WIDTH, top + SECTOR_WIDTH);
+    let (start, end) = match sector.moving {
+        Moving::Up => (vec2(left, top), vec2(right, top)),
+        Moving::Right => (vec2(right, top), vec2(right, bottom)),
+        Moving::Down => (vec2(left, bottom), vec2(right, bottom)),
+        Moving::Left => (vec2(left, top), vec2(left, bottom)),
+    };
+    draw_line(start.x, start.y, end.x, end.y, LINE_WIDTH * 2.0, MARKING);
+}
+
+// Arrows are drawn for a car moving up, in units of a sector, then rotated to the lane
+fn render_arrow(sector: &Sector, turning: &Turning) {
+    let center = vec2(
+        (sector.get_x() as f32 + 0.5) * SECTOR_WIDTH,
+        (sector.get_y() as f32 + 0.5) * SECTOR_WIDTH,
+    );
+    let rotate = |p: Vec2| -> Vec2 {
+        let p = match sector.moving {
+            Moving::Up => p,
+            Moving::Right => vec2(-p.y, p.x),
+            Moving::Down => -p,
+            Moving::Left => vec2(p.y, -p.x),
+        };
+        center + p * SECTOR_WIDTH
+    };
+    let line = |a: Vec2, b: Vec2| {
+        let (a, b) = (rotate(a), rotate(b));
+        draw_line(a.x, a.y, b.x, b.y, LINE_WIDTH, MARKING);
+    };
+    let head = |tip: Vec2, back: Vec2, side: Vec2| {
+        draw_triangle(
+            rotate(tip),
+            rotate(back + side),
+            rotate(back - side),
+            MARKING,
+        );
+    };
+
+    match turning {
+        Turning::Straight => {
+            line(vec2(0.0, 0.3), vec2(0.0, -0.15));
+            head(vec2(0.0, -0.3), vec2(0.0, -0.15), vec2(0.1, 0.0));
+        }
+        Turning::Left | Turning::Right => {
+            let side = if *turning == Turning::Left { -1.0 } else { 1.0 };
+            line(vec2(0.0, 0.3), vec2(0.0, -0.1));
+            line(vec2(0.0, -0.1), vec2(0.1 * side, -0.1));
+            head(
+                vec2(0.25 * side, -0.1),
+                vec2(0.1 * side, -0.1),
+                vec2(0.0, 0.1),
+            );
+        }
+    }
 }
@@ -1,21 +1,21 @@
-use crate::traffic::Statistics;
+use crate::traffic::{Direction, Statistics};
 use macroquad::prelude::*;
 
-use crate::config::{SECTOR_WIDTH, WINDOW_SIZE};
+use crate::config::SECTOR_WIDTH;
 use crate::render::{FONT_SIZE, TITLE_SIZE};
 
-const TEXT_X_POS: f32 = CENTER_Y - 100.0;
-const CENTER_Y: f32 = WINDOW_SIZE as f32 / 2.0;
-
 pub fn render_statistics(stats: &Statistics) {
+    let text_x_pos = screen_width() / 2.0 - 100.0;
+    let center_y = screen_height() / 2.0;
+
     // Render a translucent rectangle as a backdrop
-    draw_rectangle(0.0, 0.0, WINDOW_SIZE as f32, WINDOW_SIZE as f32, BLACK);
+    draw_rectangle(0.0, 0.0, screen_width(), screen_height(), BLACK);
 
     // Display title
     draw_text(
         "Final Statistics:",
-        TEXT_X_POS,
-        CENTER_Y - 80.0,
+        text_x_pos,
+        center_y - 80.0,
         TITLE_SIZE,
         WHITE,
     );
@@ -23,8 +23,8 @@ pub fn render_statistics(stats: &Statistics) {
     // Display statistics
     draw_text(
         &format!("Max Vehicles: {} cars", stats.max_vehicles()),
-        TEXT_X_POS,
-        CENTER_Y - 60.0,
+        text_x_pos,
+        center_y - 60.0,
         FONT_SIZE,
         WHITE,
     );
@@ -33,8 +33,8 @@ pub fn render_statistics(stats: &Statistics) {
             "Max Velocity: {} px/s",
             round_to_tenth(stats.max_velocity() * SECTOR_WIDTH)
         ),
-        TEXT_X_POS,
-        CENTER_Y - 40.0,
+        text_x_pos,
+        center_y - 40.0,
         FONT_SIZE,
         WHITE,
     );
@@ -43,39 +43,150 @@ pub fn render_statistics(stats: &Statistics) {
             "Min Velocity: {} px/s",
             round_to_tenth(stats.min_velocity() * SECTOR_WIDTH)
         ),
-        TEXT_X_POS,
-        CENTER_Y - 20.0,
+        text_x_pos,
+        center_y - 20.0,
         FONT_SIZE,
         WHITE,
     );
     draw_text(
         &format!("Max Time: {} s", round_to_tenth(stats.max_time())),
-        TEXT_X_POS,
-        CENTER_Y,
+        text_x_pos,
+        center_y,
         FONT_SIZE,
         WHITE,
     );
     draw_text(
         &format!("Min Time: {} s", round_to_tenth(stats.min_time())),
-        TEXT_X_POS,
-        CENTER_Y + 20.0,
+        text_x_pos,
+        center_y + 20.0,
         FONT_SIZE,
         WHITE,
     );
     draw_text(
         &format!("Close Calls: {}", stats.close_calls()),
-        TEXT_X_POS,
-        CENTER_Y + 40.0,
+        text_x_pos,
+        center_y + 40.0,
         FONT_SIZE,
         WHITE,
     );
     draw_text(
         &format!("Collisions: {}", stats.collisions()),
-        TEXT_X_POS,
-        CENTER_Y + 60.0,
+        text_x_pos,
+        center_y + 60.0,
+        FONT_SIZE,
+        WHITE,
+    );
+    draw_text(
+        &format!("Deadlocks: {}", stats.deadlocks().len()),
+        text_x_pos,
+        center_y + 80.0,
+        FONT_SIZE,
+        WHITE,
+    );
+    draw_text(
+        &format!(
+            "Spawns: {} delayed, {} rejected",
+            stats.delayed(),
+            stats.rejected()
+        ),
+        text_x_pos,
+        center_y + 100.0,
+        FONT_SIZE,
+        WHITE,
+    );
+    draw_text(
+        &format!(
+            "Platoons: {} cars mean, {} max, {} s gap",
+            round_to_tenth(stats.mean_platoon_size()),
+            stats.max_platoon_size(),
+            round_to_tenth(stats.mean_platoon_gap())
+        ),
+        text_x_pos,
+        center_y + 120.0,
+        FONT_SIZE,
+        WHITE,
+    );
+    draw_text(
+        &format!(
+            "Messages: {} sent, {} lost, {} s mean age  Missed detections: {}",
+            stats.messages(),
+            stats.lost_messages(),
+            round_to_tenth(stats.mean_message_age()),
+            stats.missed()
+        ),
+        text_x_pos,
+        center_y + 140.0,
+        FONT_SIZE,
+        WHITE,
+    );
+    for (i, direction) in Direction::ALL.iter().enumerate() {
+        let (mean, max) = stats.approach_wait(direction);
+        draw_text(
+            &format!(
+                "Wait {direction:?}: {} s mean, {} s max",
+                round_to_tenth(mean),
+                round_to_tenth(max)
+            ),
+            text_x_pos,
+            center_y + 160.0 + i as f32 * 20.0,
+            FONT_SIZE,
+            WHITE,
+        );
+    }
+
+    if stats.samples().len() > 1 {
+        let chart = Rect::new(text_x_pos, center_y + 250.0, 400.0, 120.0);
+        render_queue_chart(stats, chart);
+    }
+}
+
+/// Colors of the approaches in the order of `Direction::ALL`.
+const APPROACH_COLORS: [Color; 4] = [SKYBLUE, ORANGE, GREEN, PINK];
+
+/// ### render_queue_chart
+/// Draw the queue of every approach over the `Sample`s of a run inside `chart`, so a peak in
+/// demand shows up as a peak in the queue of its approaches.
+fn render_queue_chart(stats: &Statistics, chart: Rect) {
+    let samples = stats.samples();
+    let end = samples.last().map_or(1.0, |sample| sample.time).max(1.0);
+    let peak = samples
+        .iter()
+        .flat_map(|sample| sample.queue)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    draw_rectangle_lines(chart.x, chart.y, chart.w, chart.h, 1.0, GRAY);
+    draw_text(
+        &format!(
+            "Queue over {} min, peak {peak} cars",
+            round_to_tenth(end / 60.0)
+        ),
+        chart.x,
+        chart.y - 5.0,
         FONT_SIZE,
         WHITE,
     );
+    let point = |time: f32, queue: usize| {
+        vec2(
+            chart.x + time / end * chart.w,
+            chart.bottom() - queue as f32 / peak as f32 * chart.h,
+        )
+    };
+    for (i, (direction, color)) in Direction::ALL.iter().zip(APPROACH_COLORS).enumerate() {
+        for pair in samples.windows(2) {
+            let a = point(pair[0].time, pair[0].queue[i]);
+            let b = point(pair[1].time, pair[1].queue[i]);
+            draw_line(a.x, a.y, b.x, b.y, 2.0, color);
+        }
+        draw_text(
+            &format!("{direction:?}"),
+            chart.right() + 10.0,
+            chart.y + (i + 1) as f32 * FONT_SIZE,
+            FONT_SIZE,
+            color,
+        );
+    }
 }
 
 pub fn round_to_tenth(num: f32) -> f32 {
@@ -1,26 +1,114 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
 use macroquad::prelude::*;
 
-const CAR_SPRITES: [&str; 3] = [
-    "assets/cars/Car_Sprite_Sheet_crop.png",
-    "assets/cars/Audi_Sprite_Sheet_crop.png",
-    "assets/cars/Viper_Sprite_Sheet_crop.png",
+/// A sprite embedded in the binary, and the path it can be overridden from
+/// relative to an assets directory.
+struct Sprite {
+    path: &'static str,
+    bytes: &'static [u8],
+}
+
+const BACKGROUND: Sprite = Sprite {
+    path: "intersection.png",
+    bytes: include_bytes!("../../assets/intersection.png"),
+};
+
+const CAR_SPRITES: [Sprite; 3] = [
+    Sprite {
+        path: "cars/Car_Sprite_Sheet_crop.png",
+        bytes: include_bytes!("../../assets/cars/Car_Sprite_Sheet_crop.png"),
+    },
+    Sprite {
+        path: "cars/Audi_Sprite_Sheet_crop.png",
+        bytes: include_bytes!("../../assets/cars/Audi_Sprite_Sheet_crop.png"),
+    },
+    Sprite {
+        path: "cars/Viper_Sprite_Sheet_crop.png",
+        bytes: include_bytes!("../../assets/cars/Viper_Sprite_Sheet_crop.png"),
+    },
 ];
 
+#[derive(Debug)]
+pub enum TextureError {
+    /// A sprite in the assets directory exists but could not be read.
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// A sprite could not be decoded as an image.
+    Decode {
+        path: PathBuf,
+        source: macroquad::Error,
+    },
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Read { path, source } => {
+                write!(f, "could not read {}: {source}", path.display())
+            }
+            TextureError::Decode { path, source } => {
+                write!(f, "could not decode {}: {source}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Read { source, .. } => Some(source),
+            TextureError::Decode { source, .. } => Some(source),
+        }
+    }
+}
+
+/// ### Textures
+/// Sprites for the roads and cars. A missing sprite is `None`, and is drawn as a simple shape.
 #[derive(PartialEq, Clone)]
 pub struct Textures {
-    pub bg: Texture2D,
-    pub cars: Vec<Texture2D>,
+    pub bg: Option<Texture2D>,
+    pub cars: Vec<Option<Texture2D>>,
 }
 
 impl Textures {
-    pub async fn load() -> Self {
-        let mut cars = Vec::new();
-        for sprite in CAR_SPRITES {
-            cars.push(load_texture(sprite).await.unwrap())
-        }
-        Self {
-            bg: load_texture("assets/intersection.png").await.unwrap(),
-            cars,
-        }
+    /// ### load
+    /// Load the sprites embedded in the binary. Sprites found in `assets`, if given, are used
+    /// instead of the embedded ones. A sprite that fails to load is left `None`, so only that
+    /// sprite is drawn as a simple shape, and its error is returned alongside the textures.
+    pub fn load(assets: Option<&Path>) -> (Self, Vec<TextureError>) {
+        let mut errors = Vec::new();
+        let mut load = |sprite: &Sprite| {
+            read_sprite(sprite, assets)
+                .map_err(|err| errors.push(err))
+                .ok()
+        };
+        let textures = Self {
+            bg: load(&BACKGROUND),
+            cars: CAR_SPRITES.iter().map(&mut load).collect(),
+        };
+        (textures, errors)
     }
 }
+
+fn read_sprite(sprite: &Sprite, assets: Option<&Path>) -> Result<Texture2D, TextureError> {
+    let path = match assets {
+        Some(dir) => dir.join(sprite.path),
+        None => PathBuf::from(sprite.path),
+    };
+    let bytes = match assets.filter(|_| path.exists()) {
+        Some(_) => Cow::Owned(std::fs::read(&path).map_err(|source| TextureError::Read {
+            path: path.clone(),
+            source,
+        })?),
+        None => Cow::Borrowed(sprite.bytes),
+    };
+
+    let image = Image::from_file_with_format(&bytes, None)
+        .map_err(|source| TextureError::Decode { path, source })?;
+    Ok(Texture2D::from_image(&image))
+}
@@ -0,0 +1,70 @@
+use macroquad::prelude::*;
+
+use crate::render::statistics::round_to_tenth;
+use crate::render::FONT_SIZE;
+use crate::traffic::replay::Replay;
+use crate::traffic::Clock;
+
+const HEIGHT: f32 = 40.0;
+const MARGIN: f32 = 20.0;
+
+/// ### timeline_bar
+/// The bar along the bottom of the screen that the time of a replay is drawn on.
+pub fn timeline_bar() -> Rect {
+    Rect::new(
+        MARGIN,
+        screen_height() - HEIGHT + 10.0,
+        screen_width() - 2.0 * MARGIN,
+        8.0,
+    )
+}
+
+/// ### timeline_time
+/// The time in the replay under the horizontal screen position `x`.
+pub fn timeline_time(replay: &Replay, x: f32) -> f32 {
+    let bar = timeline_bar();
+    ((x - bar.x) / bar.w).clamp(0.0, 1.0) * replay.duration()
+}
+
+/// ### render_timeline
+/// Draw the timeline of a replay, with a mark at every collision (red) and close call (yellow),
+/// and the playback mode and time.
+pub fn render_timeline(replay: &Replay, clock: &Clock) {
+    let bar = timeline_bar();
+    let duration = replay.duration().max(f32::EPSILON);
+    let at = |time: f32| bar.x + time / duration * bar.w;
+
+    draw_rectangle(
+        0.0,
+        screen_height() - HEIGHT - FONT_SIZE,
+        screen_width(),
+        HEIGHT + FONT_SIZE,
+        Color::new(0.0, 0.0, 0.0, 0.6),
+    );
+    draw_rectangle(bar.x, bar.y, bar.w, bar.h, DARKGRAY);
+    draw_rectangle(bar.x, bar.y, at(replay.time()) - bar.x, bar.h, LIGHTGRAY);
+    for (time, collision) in replay.incidents() {
+        let color = if collision { RED } else { YELLOW };
+        draw_line(
+            at(time),
+            bar.y - 4.0,
+            at(time),
+            bar.y + bar.h + 4.0,
+            2.0,
+            color,
+        );
+    }
+    draw_circle(at(replay.time()), bar.y + bar.h / 2.0, bar.h, WHITE);
+
+    let mode = match (clock.paused, replay.reverse) {
+        (true, _) => "PAUSED".to_string(),
+        (false, false) => format!("{}x", clock.time_scale),
+        (false, true) => format!("-{}x", clock.time_scale),
+    };
+    let text = format!(
+        "REPLAY {mode} | {} / {} s",
+        round_to_tenth(replay.time()),
+        round_to_tenth(replay.duration())
+    );
+    draw_text(&text, MARGIN, bar.y - 10.0, FONT_SIZE, WHITE);
+}
@@ -1,21 +1,64 @@
 use macroquad::rand::gen_range;
-use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+
+use std::sync::OnceLock;
 
 use crate::traffic::path::{Path, Sector};
-use crate::traffic::{Direction, Statistics};
+use crate::traffic::perception::Perception;
+use crate::traffic::{Direction, Perceived, SpatialIndex, Statistics};
 
 use crate::config::{
-    ACCELERATION_DISTANCE, CLOSE_CALL_DISTANCE, FPS, MAX_VELOCITY, SCAN_DISTANCE, SECTOR_WIDTH,
-    SPEED_LIMIT, WINDOW_SIZE,
+    ACCELERATION_DISTANCE, CLOSE_CALL_DISTANCE, DT, FPS, MAX_VELOCITY, SCAN_DISTANCE, SECTOR_WIDTH,
+    SPEED_LIMIT, WORLD_SIZE,
 };
 
-#[derive(Eq, PartialEq, Clone, Debug)]
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum Turning {
     Left,
     Straight,
     Right,
 }
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl Turning {
+    pub const ALL: [Turning; 3] = [Turning::Left, Turning::Straight, Turning::Right];
+
+    /// ### lane
+    /// Index of the lane in a `Road` used by cars with this `Turning`.
+    pub fn lane(&self) -> usize {
+        match self {
+            Turning::Left => 0,
+            Turning::Straight => 1,
+            Turning::Right => 2,
+        }
+    }
+
+    /// ### choose
+    /// Pick one of `turnings` at random, weighted by the relative probability in `weights` of
+    /// turning left, going straight and turning right. Draws from the same random numbers as
+    /// the rest of the simulation, so seeded runs pick the same lanes. Returns `None` when none
+    /// of `turnings` has a positive weight.
+    pub fn choose(turnings: &[Turning], weights: [f32; 3]) -> Option<Turning> {
+        let weight = |turning: &Turning| match weights[turning.lane()] {
+            w if w.is_finite() && w > 0.0 => w,
+            _ => 0.0,
+        };
+        let total: f32 = turnings.iter().map(weight).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut left = gen_range(0.0, total);
+        let mut chosen = None;
+        for turning in turnings.iter().filter(|t| weight(t) > 0.0) {
+            chosen = Some(*turning);
+            left -= weight(turning);
+            if left < 0.0 {
+                break;
+            }
+        }
+        chosen
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Moving {
     Up,
     Right,
@@ -30,7 +73,7 @@ pub struct Borders {
     pub(crate) bottom: f32,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Model {
     Standard,
     Audi,
@@ -46,11 +89,71 @@ pub struct Car {
     pub moving: Moving,
     pub vel: f32,
     pub turning: Turning,
-    pub path: Path,
+    pub path: &'static Path,
     pub direction: Direction,
     pub id: usize,
-    time: SystemTime,
+    spawned: u64,
     pub model: Model,
+    /// Id of the car this car braked for during the last tick, if any.
+    pub braking_for: Option<usize>,
+    /// Why the car braked during the last tick, if it did.
+    pub brake_reason: Option<BrakeReason>,
+    /// Number of ticks the car has stood still.
+    pub waited: u64,
+    /// The car has waited longer than the maximum wait, and cars from other directions
+    /// give way to it.
+    pub priority: bool,
+    /// Id of the leader of the platoon the car is in, its own id if it leads one.
+    pub platoon: Option<usize>,
+    /// Id of the car in front of it in its platoon, which it follows.
+    pub ahead: Option<usize>,
+    /// Id of the car the `Watchdog` has the car hold for, if any.
+    pub holding_for: Option<usize>,
+    /// The `Watchdog` gave the car right of way over the cars holding for it.
+    pub right_of_way: bool,
+}
+
+/// ### BrakeReason
+/// The decision that made a car brake or stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrakeReason {
+    /// The next sector in the `Path` is taken, from `move_in_path`.
+    CarAhead,
+    /// A car close in front, from `forward_scan`.
+    ForwardScan,
+    /// A car with a crossing path closer to its exit, from `ray_casting`.
+    RayCasting,
+    /// A car going straight is passing through the intersection, from `check_passing`.
+    Passing,
+    /// A car in the sector in front, from `sector_in_front`.
+    SectorInFront,
+    /// Another car is turning left through the middle, from `center_scan`.
+    CenterScan,
+    /// Holding for a car the `Watchdog` gave right of way, from `Watchdog::check`.
+    RightOfWay,
+    /// Holding at the stop line for a car with priority, from `give_way`.
+    GiveWay,
+    /// Keeping the time gap to the car in front in its platoon, from `follow`.
+    Platoon,
+}
+
+/// ### CarView
+/// Read-only snapshot of the parts of a `Car` that other cars base their decisions on.
+/// It is `Copy` and shares the interned `Path` of the car, so taking one does not allocate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CarView {
+    pub id: usize,
+    pub x: f32,
+    pub y: f32,
+    pub vel: f32,
+    pub index: usize,
+    pub moving: Moving,
+    pub turning: Turning,
+    pub direction: Direction,
+    pub path: &'static Path,
+    pub priority: bool,
+    pub platoon: Option<usize>,
+    pub holding_for: Option<usize>,
 }
 
 impl PartialEq for Car {
@@ -61,7 +164,7 @@ impl PartialEq for Car {
 
 impl Car {
     pub fn new(direction: Direction, turning: Turning, id: usize) -> Car {
-        let path = Path::new(&direction, &turning);
+        let path = Path::get(&direction, &turning);
         let (x, y) = get_entry_coords(&path.sectors[0], &direction);
         Car {
             x,
@@ -78,7 +181,15 @@ impl Car {
             turning,
             path,
             direction,
-            time: SystemTime::now(),
+            spawned: 0,
+            braking_for: None,
+            brake_reason: None,
+            waited: 0,
+            priority: false,
+            platoon: None,
+            ahead: None,
+            holding_for: None,
+            right_of_way: false,
             model: match gen_range(0, 5) {
                 0 => Model::Viper,
                 1 => Model::Audi,
@@ -89,10 +200,12 @@ impl Car {
 
     /// ### move_car
     /// Move the car in `Path` and also in `Car.x` and `Car.y`.
-    pub fn move_car(&mut self, all_cars: &[Car]) {
-        self.move_in_path(all_cars);
+    pub fn move_car(&mut self, cars: &Perceived) {
+        self.braking_for = None;
+        self.brake_reason = None;
+        self.move_in_path(cars);
         self.moving = self.sector(0).moving;
-        self.change_pos(all_cars);
+        self.change_pos(cars);
 
         // car is turning right, no further logic needed
         if self.turning == Turning::Right {
@@ -100,26 +213,27 @@ impl Car {
             return;
         }
 
-        // car is still entering the intersection
+        // car is still entering the intersection, only a platoon to catch up with
         if self.index < 2 {
+            self.follow(cars);
             return;
         }
 
         if self.turning == Turning::Straight && (3..=7).contains(&self.index) {
-            self.sector_in_front(all_cars);
+            self.sector_in_front(cars);
         }
 
         if self.index == 3 && self.sector_pos() > CLOSE_CALL_DISTANCE {
-            self.check_passing(all_cars);
+            self.check_passing(cars);
         }
 
         if self.turning == Turning::Left && (5..=7).contains(&self.index) {
-            self.center_scan(all_cars);
+            self.center_scan(cars);
         }
 
         // car going straight has reached the other side of the intersection
         if self.index >= 8 {
-            self.forward_scan(all_cars);
+            self.keep_distance(cars);
             return;
         }
 
@@ -127,10 +241,18 @@ impl Car {
         self.adjust_position();
 
         // send rays a certain distance and check for cars
-        self.ray_casting(all_cars);
+        self.ray_casting(cars);
 
         // scan in front of car to see if it is safe to accelerate, or if it should stop
-        self.forward_scan(all_cars);
+        self.keep_distance(cars);
+    }
+
+    /// ### keep_distance
+    /// Follow the car in front in the platoon, or scan in front when not in a platoon.
+    fn keep_distance(&mut self, cars: &Perceived) {
+        if !self.follow(cars) {
+            self.forward_scan(cars);
+        }
     }
 
     pub fn accelerate(&mut self, distance: f32) {
@@ -160,11 +282,18 @@ impl Car {
         self.vel = 0.0;
     }
 
+    /// ### braked
+    /// Record why the car braked and for which car.
+    pub fn braked(&mut self, reason: BrakeReason, car: Option<usize>) {
+        self.brake_reason = Some(reason);
+        self.braking_for = car;
+    }
+
     /// ### change_pos
     /// Change position of car. Will go faster if no cars around and slower if too many cars around.
-    fn change_pos(&mut self, cars: &[Car]) {
+    fn change_pos(&mut self, cars: &Perceived) {
         let x = match cars
-            .iter()
+            .nearby(self.center_car(), ACCELERATION_DISTANCE)
             .filter(|c| self.id != c.id && self.calc_dist(c) < ACCELERATION_DISTANCE)
             .count()
         {
@@ -183,58 +312,57 @@ impl Car {
     /// ### sector_position
     /// Get the distance travelled into a `Sector`. This is used to break deadlocks.
     pub fn sector_pos(&self) -> f32 {
-        match self.moving {
-            Moving::Up => SECTOR_WIDTH - (self.y - self.sector(0).get_y() as f32 * SECTOR_WIDTH),
-            Moving::Right => SECTOR_WIDTH - (self.sector(0).get_x() as f32 * SECTOR_WIDTH - self.x),
-            Moving::Down => SECTOR_WIDTH - (self.sector(0).get_y() as f32 * SECTOR_WIDTH - self.y),
-            Moving::Left => SECTOR_WIDTH - (self.x - self.sector(0).get_x() as f32 * SECTOR_WIDTH),
-        }
+        self.view().sector_pos()
     }
 
     /// ### move_in_path
     /// Moves the car inside its own `Path` by incrementing `path.current`.
     /// Stop if a car in sector ahead.
-    fn move_in_path(&mut self, cars: &[Car]) {
+    fn move_in_path(&mut self, cars: &Perceived) {
         if self.index + 2 > self.path.sectors.len() {
             return;
         }
-        let car_ahead = cars.iter().any(|c| c.sector(0) == self.sector(1));
+        let car_ahead = cars.in_sector(&self.sector(1)).next().map(|c| c.id);
 
         let next = &self.sector(0);
         match self.moving {
             Moving::Up => {
                 if self.update_up(next) {
-                    if !car_ahead {
+                    if car_ahead.is_none() {
                         self.index += 1;
                     } else {
                         self.stop();
+                        self.braked(BrakeReason::CarAhead, car_ahead);
                     }
                 }
             }
             Moving::Right => {
                 if self.update_right(next) {
-                    if !car_ahead {
+                    if car_ahead.is_none() {
                         self.index += 1;
                     } else {
                         self.stop();
+                        self.braked(BrakeReason::CarAhead, car_ahead);
                     }
                 }
             }
             Moving::Down => {
                 if self.update_down(next) {
-                    if !car_ahead {
+                    if car_ahead.is_none() {
                         self.index += 1;
                     } else {
                         self.stop();
+                        self.braked(BrakeReason::CarAhead, car_ahead);
                     }
                 }
             }
             Moving::Left => {
                 if self.update_left(next) {
-                    if !car_ahead {
+                    if car_ahead.is_none() {
                         self.index += 1;
                     } else {
                         self.stop();
+                        self.braked(BrakeReason::CarAhead, car_ahead);
                     }
                 }
             }
@@ -278,23 +406,82 @@ impl Car {
     /// ### get_sector
     /// Get the sector of a `Car` specified by `n`.
     pub fn sector(&self, n: usize) -> Sector {
-        self.path.sectors[self.index + n].clone()
+        self.view().sector(n)
     }
 
     /// ### get_borders
     /// Get the borders of a `Car`.
     pub fn borders(&self) -> Borders {
-        Borders {
-            top: self.y,
-            right: self.x + SECTOR_WIDTH,
-            bottom: self.y + SECTOR_WIDTH,
-            left: self.x,
+        self.view().borders()
+    }
+
+    /// ### view
+    /// Take a read-only `CarView` of the car.
+    pub fn view(&self) -> CarView {
+        CarView {
+            id: self.id,
+            x: self.x,
+            y: self.y,
+            vel: self.vel,
+            index: self.index,
+            moving: self.moving,
+            turning: self.turning,
+            direction: self.direction,
+            path: self.path,
+            priority: self.priority,
+            platoon: self.platoon,
+            holding_for: self.holding_for,
         }
     }
 
-    pub fn add_time(&self, stats: &mut Statistics) {
-        let duration = SystemTime::now().duration_since(self.time).unwrap();
-        stats.set_time(duration.as_secs_f32());
+    /// ### spawned_at
+    /// Set the simulation tick the car entered the world.
+    pub fn spawned_at(mut self, tick: u64) -> Car {
+        self.spawned = tick;
+        self
+    }
+
+    /// ### time_in_system
+    /// Simulated seconds since the car was spawned, at simulation tick `tick`.
+    pub fn time_in_system(&self, tick: u64) -> f32 {
+        tick.saturating_sub(self.spawned) as f32 * DT
+    }
+
+    /// ### delay
+    /// Simulated seconds the car has taken longer than `free_flow_time`, at simulation tick
+    /// `tick`.
+    pub fn delay(&self, tick: u64) -> f32 {
+        (self.time_in_system(tick) - Car::free_flow_time(self.direction, self.turning)).max(0.0)
+    }
+
+    /// ### free_flow_time
+    /// Simulated seconds a car from `direction` takes to drive through the world when it is
+    /// the only car in it. There are only 12 different paths, so every time is driven once.
+    pub fn free_flow_time(direction: Direction, turning: Turning) -> f32 {
+        static TIMES: OnceLock<Vec<f32>> = OnceLock::new();
+        let times = TIMES.get_or_init(|| {
+            let (cars, perception) = (SpatialIndex::default(), Perception::default());
+            Direction::ALL
+                .iter()
+                .flat_map(|direction| Turning::ALL.iter().map(move |turning| (direction, turning)))
+                .map(|(&direction, &turning)| {
+                    let mut car = Car::new(direction, turning, 0);
+                    let mut ticks = 0;
+                    while !car.is_done() {
+                        car.move_car(&perception.perceive(&car, &cars, ticks));
+                        ticks += 1;
+                    }
+                    ticks as f32 * DT
+                })
+                .collect()
+        });
+        times[direction.index() * 3 + turning.lane()]
+    }
+
+    pub fn add_time(&self, stats: &mut Statistics, tick: u64) {
+        stats.set_exit(tick as f32 * DT, self.time_in_system(tick));
+        stats.set_delay(&self.direction, self.delay(tick));
+        stats.set_wait(&self.direction, self.waited as f32 * DT);
     }
 
     /// ### is_done
@@ -302,13 +489,64 @@ impl Car {
     pub fn is_done(&self) -> bool {
         match self.moving {
             Moving::Up => self.borders().bottom <= 0.0,
-            Moving::Right => self.borders().left >= WINDOW_SIZE as f32,
-            Moving::Down => self.borders().top >= WINDOW_SIZE as f32,
+            Moving::Right => self.borders().left >= WORLD_SIZE,
+            Moving::Down => self.borders().top >= WORLD_SIZE,
             Moving::Left => self.borders().right <= 0.0,
         }
     }
 }
 
+impl CarView {
+    /// ### get_sector
+    /// Get the sector of a `CarView` specified by `n`.
+    pub fn sector(&self, n: usize) -> Sector {
+        self.path.sectors[self.index + n]
+    }
+
+    /// ### sector_position
+    /// Get the distance travelled into a `Sector`.
+    pub fn sector_pos(&self) -> f32 {
+        match self.moving {
+            Moving::Up => SECTOR_WIDTH - (self.y - self.sector(0).get_y() as f32 * SECTOR_WIDTH),
+            Moving::Right => SECTOR_WIDTH - (self.sector(0).get_x() as f32 * SECTOR_WIDTH - self.x),
+            Moving::Down => SECTOR_WIDTH - (self.sector(0).get_y() as f32 * SECTOR_WIDTH - self.y),
+            Moving::Left => SECTOR_WIDTH - (self.x - self.sector(0).get_x() as f32 * SECTOR_WIDTH),
+        }
+    }
+
+    /// ### get_borders
+    /// Get the borders of a `CarView`.
+    pub fn borders(&self) -> Borders {
+        Borders {
+            top: self.y,
+            right: self.x + SECTOR_WIDTH,
+            bottom: self.y + SECTOR_WIDTH,
+            left: self.x,
+        }
+    }
+
+    /// ### center_car
+    /// get the center point of a car
+    pub fn center_car(&self) -> (f32, f32) {
+        let Borders {
+            top,
+            right,
+            bottom,
+            left,
+        } = self.borders();
+        (left + ((right - left) / 2.0), top + ((bottom - top) / 2.0))
+    }
+
+    /// ### calculate_distance
+    /// Distance between the center points of two cars.
+    pub fn calc_dist(&self, other: &CarView) -> f32 {
+        let (x, y) = self.center_car();
+        let (x2, y2) = other.center_car();
+        let (dx, dy) = ((x - x2).abs(), (y - y2).abs());
+        (dx * dx + dy * dy).sqrt()
+    }
+}
+
 fn get_entry_coords(p: &Sector, direction: &Direction) -> (f32, f32) {
     match direction {
         Direction::West => (
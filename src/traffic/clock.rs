@@ -0,0 +1,70 @@
+use crate::config::{FPS, MAX_TICKS_PER_FRAME, MAX_TIME_SCALE, MIN_TIME_SCALE};
+
+/// ### Clock
+/// Fixed-timestep accumulator. Real time is added every frame, scaled by `time_scale`,
+/// and the simulation is advanced in whole ticks of `DT`. The remainder is carried over
+/// to the next frame, so the simulation results do not depend on the frame rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clock {
+    accumulator: f64,
+    pub time_scale: f32,
+    pub paused: bool,
+    steps: u32,
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock {
+            accumulator: 0.0,
+            time_scale: 1.0,
+            paused: false,
+            steps: 0,
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.accumulator = 0.0;
+    }
+
+    /// ### step
+    /// Simulate a single tick on the next `advance` while paused.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.steps += 1;
+        }
+    }
+
+    pub fn speed_up(&mut self) {
+        self.time_scale = (self.time_scale * 2.0).min(MAX_TIME_SCALE);
+    }
+
+    pub fn slow_down(&mut self) {
+        self.time_scale = (self.time_scale / 2.0).max(MIN_TIME_SCALE);
+    }
+
+    /// ### advance
+    /// Add `frame_time` seconds of real time and get the number of ticks to simulate.
+    pub fn advance(&mut self, frame_time: f32) -> u32 {
+        if self.paused {
+            return std::mem::take(&mut self.steps);
+        }
+
+        // `DT` in double precision, so whole seconds add up to whole ticks
+        let dt = 1.0 / FPS as f64;
+        self.accumulator += frame_time as f64 * self.time_scale as f64;
+        let ticks = (self.accumulator / dt).floor();
+        self.accumulator -= ticks * dt;
+
+        if ticks > MAX_TICKS_PER_FRAME as f64 {
+            return MAX_TICKS_PER_FRAME;
+        }
+        ticks as u32
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,5 +1,7 @@
 use crate::config::{
-    ACCELERATION_DISTANCE, CRUISE_SPEED, MARGIN, SCAN_DISTANCE, SECTOR_WIDTH, WINDOW_SIZE,
+    ACCELERATION_DISTANCE, CLOSE_CALL_DISTANCE, CRUISE_SPEED, FPS, MARGIN, MAX_VELOCITY,
+    PLATOON_DISTANCE, PLATOON_GAP, PLATOON_SPACING, SCAN_DISTANCE, SECTOR_WIDTH, SPEED_LIMIT,
+    WORLD_SIZE,
 };
 use crate::traffic::*;
 
@@ -7,15 +9,20 @@ impl Car {
     /// ### forward_scan
     /// Scans the sectors in front of the car and accelerate depending on the distance
     /// to the closest car in front
-    pub fn forward_scan(&mut self, cars: &[Car]) {
+    pub fn forward_scan(&mut self, cars: &Perceived) {
         // Get the ranges where we scan cars in front
         let scan_x = self.borders().left + MARGIN..=self.borders().right - MARGIN;
         let scan_y = self.borders().top + MARGIN..=self.borders().bottom - MARGIN;
 
-        // The longest distance to car in front.
-        let mut distance = WINDOW_SIZE as f32;
-        for car in cars.iter().filter(|c| c.id != self.id) {
-            if self.calc_dist(car) > distance {
+        // The longest distance to car in front. Cars further away than `SCAN_DISTANCE` have
+        // the same effect on acceleration as no car at all, so only those need to be scanned.
+        let mut distance = WORLD_SIZE;
+        let mut closest = None;
+        for car in cars
+            .nearby(self.center_car(), SCAN_DISTANCE)
+            .filter(|c| c.id != self.id)
+        {
+            if self.calc_dist(&car) > distance {
                 continue;
             }
 
@@ -24,22 +31,22 @@ impl Car {
             match self.moving {
                 Moving::Up => {
                     if y < self_y && scan_x.contains(&x) {
-                        distance = self.calc_dist(car);
+                        (distance, closest) = (self.calc_dist(&car), Some(car.id));
                     }
                 }
                 Moving::Down => {
                     if y > self_y && scan_x.contains(&x) {
-                        distance = self.calc_dist(car);
+                        (distance, closest) = (self.calc_dist(&car), Some(car.id));
                     }
                 }
                 Moving::Right => {
                     if x > self_x && scan_y.contains(&y) {
-                        distance = self.calc_dist(car);
+                        (distance, closest) = (self.calc_dist(&car), Some(car.id));
                     }
                 }
                 Moving::Left => {
                     if x < self_x && scan_y.contains(&y) {
-                        distance = self.calc_dist(car);
+                        (distance, closest) = (self.calc_dist(&car), Some(car.id));
                     }
                 }
             }
@@ -48,6 +55,7 @@ impl Car {
             self.accelerate(distance);
         } else {
             self.brake(distance);
+            self.braked(BrakeReason::ForwardScan, closest);
         }
     }
 
@@ -55,16 +63,17 @@ impl Car {
     /// Check if there are any cars in front of self are inside the `SCAN_DISTANCE`.
     /// If these cars have a shorter distance to the exit than self, brake according to the closest
     /// of these cars.
-    pub fn ray_casting(&mut self, cars: &[Car]) {
+    pub fn ray_casting(&mut self, cars: &Perceived) {
         // Loop through all cars which are within collision range (one sector)
         let mut distance = SCAN_DISTANCE;
-        for car in cars.iter().filter(|c| {
+        let mut closest = None;
+        for car in cars.nearby(self.center_car(), SCAN_DISTANCE).filter(|c| {
             self.longer_distance_to_exit(c)
                 && self.calc_dist(c) < SCAN_DISTANCE
                 && self.crossing_paths(c)
         }) {
             // Only brake according to shortest distance
-            if self.calc_dist(car) > distance {
+            if self.calc_dist(&car) > distance {
                 continue;
             }
 
@@ -74,22 +83,22 @@ impl Car {
             match self.moving {
                 Moving::Up => {
                     if y > y2 {
-                        distance = self.calc_dist(car);
+                        (distance, closest) = (self.calc_dist(&car), Some(car.id));
                     }
                 }
                 Moving::Down => {
                     if y < y2 {
-                        distance = self.calc_dist(car);
+                        (distance, closest) = (self.calc_dist(&car), Some(car.id));
                     }
                 }
                 Moving::Right => {
                     if x < x2 {
-                        distance = self.calc_dist(car);
+                        (distance, closest) = (self.calc_dist(&car), Some(car.id));
                     }
                 }
                 Moving::Left => {
                     if x > x2 {
-                        distance = self.calc_dist(car);
+                        (distance, closest) = (self.calc_dist(&car), Some(car.id));
                     }
                 }
             }
@@ -97,16 +106,17 @@ impl Car {
 
         if distance < SCAN_DISTANCE {
             self.brake(distance);
+            self.braked(BrakeReason::RayCasting, closest);
         }
     }
 
-    pub fn check_passing(&mut self, cars: &[Car]) {
+    pub fn check_passing(&mut self, cars: &Perceived) {
         let index = if self.turning == Turning::Straight {
             6..=8
         } else {
             5..=7
         };
-        if cars.iter().any(|c| {
+        if let Some(car) = cars.nearby(self.center_car(), SCAN_DISTANCE).find(|c| {
             c.id != self.id
                 && c.turning == Turning::Straight
                 && self.direction != c.direction
@@ -114,21 +124,20 @@ impl Car {
                 && index.contains(&c.index)
         }) {
             self.stop();
+            self.braked(BrakeReason::Passing, Some(car.id));
         }
     }
 
-    pub fn sector_in_front(&mut self, cars: &[Car]) {
-        if let Some(car) = cars
-            .iter()
-            .find(|c| c.id != self.id && self.sector(1).eq(&c.sector(0)))
-        {
-            self.brake(self.calc_dist(car));
+    pub fn sector_in_front(&mut self, cars: &Perceived) {
+        if let Some(car) = cars.in_sector(&self.sector(1)).find(|c| c.id != self.id) {
+            self.brake(self.calc_dist(&car));
+            self.braked(BrakeReason::SectorInFront, Some(car.id));
         }
     }
 
     /// ### crossing_paths
     /// Check if a car has a crossing path with self
-    fn crossing_paths(&self, other: &Car) -> bool {
+    fn crossing_paths(&self, other: &CarView) -> bool {
         for sector in &self.path.sectors[self.index..=self.index + 2] {
             if sector.eq(&other.sector(1))
                 || (sector.eq(&other.sector(0)) && other.sector_pos() < SECTOR_WIDTH / 2.0)
@@ -141,30 +150,121 @@ impl Car {
 
     /// ### longer_distance_to_exit
     /// Check if `self` has a longer distance to the exit than `other`
-    fn longer_distance_to_exit(&self, other: &Car) -> bool {
+    fn longer_distance_to_exit(&self, other: &CarView) -> bool {
         self.path.sectors.len() as f32 * SECTOR_WIDTH
             - (self.index as f32 * SECTOR_WIDTH + self.sector_pos())
             > other.path.sectors.len() as f32 * SECTOR_WIDTH
                 - (other.index as f32 * SECTOR_WIDTH + other.sector_pos())
     }
 
-    pub fn center_scan(&mut self, cars: &[Car]) {
-        if cars
-            .iter()
-            .any(|c| self.id < c.id && (5..=7).contains(&c.index) && c.turning == Turning::Left)
+    /// ### center_scan
+    /// Left turns through the middle of the intersection, which is where all left-turning cars
+    /// at index 5-7 are, so only the `MIDDLE_SECTORS` need to be scanned.
+    pub fn center_scan(&mut self, cars: &Perceived) {
+        if let Some(car) = cars
+            .in_middle()
+            .find(|c| self.yields_to(c) && (5..=7).contains(&c.index) && c.turning == Turning::Left)
         {
             self.vel = CRUISE_SPEED;
+            self.braked(BrakeReason::CenterScan, Some(car.id));
+        }
+    }
+
+    /// ### yields_to
+    /// A car with priority goes before one without, otherwise the car with the highest id goes.
+    fn yields_to(&self, other: &CarView) -> bool {
+        match (self.priority, other.priority) {
+            (false, true) => true,
+            (true, false) => false,
+            _ => self.id < other.id,
+        }
+    }
+
+    /// ### give_way
+    /// Hold at the stop line while a car from another direction with priority has not entered
+    /// the intersection yet, so it gets a gap to go through. A car following its platoon into
+    /// the intersection does not hold.
+    pub fn give_way(&mut self, cars: &Perceived) {
+        if self.priority || self.index != APPROACH_LENGTH - 1 || self.is_granted(cars) {
+            return;
+        }
+        // Only the approaches of the other directions need to be scanned
+        let approaches = Direction::ALL
+            .iter()
+            .filter(|&direction| *direction != self.direction)
+            .flat_map(|direction| Turning::ALL.iter().map(move |turning| (direction, turning)))
+            .flat_map(|(direction, turning)| {
+                Path::get(direction, turning).sectors[..APPROACH_LENGTH].iter()
+            });
+        if let Some(car) = approaches
+            .flat_map(|sector| cars.in_sector(sector))
+            .find(|c| c.priority && c.direction != self.direction && c.index < APPROACH_LENGTH)
+        {
+            self.stop();
+            self.braked(BrakeReason::GiveWay, Some(car.id));
         }
     }
 
+    /// ### follow
+    /// Match the velocity of the car in front in the platoon, and close or open the distance
+    /// to it until it is `PLATOON_SPACING` plus `PLATOON_GAP` seconds at its velocity, on the
+    /// approach and after the intersection. Braking for anything else this tick goes first.
+    /// Returns `false` if the car is not following.
+    pub fn follow(&mut self, cars: &Perceived) -> bool {
+        // Inside the intersection every car scans for itself, so crossing traffic can still
+        // split a platoon
+        if (APPROACH_LENGTH..8).contains(&self.index) {
+            return false;
+        }
+        let Some(ahead) = self.car_ahead(cars) else {
+            return false;
+        };
+        let distance = self.calc_dist(&ahead);
+        if distance <= CLOSE_CALL_DISTANCE + MARGIN {
+            self.stop();
+            self.braked(BrakeReason::Platoon, Some(ahead.id));
+            return true;
+        }
+
+        // World units per second at a velocity of 1
+        let speed = MAX_VELOCITY * FPS as f32;
+        let desired = PLATOON_SPACING + PLATOON_GAP * ahead.vel * speed;
+        let mut target = (ahead.vel + (distance - desired) / speed).clamp(0.0, SPEED_LIMIT);
+        if target < 0.3 && ahead.vel == 0.0 {
+            target = 0.0;
+        }
+
+        if self.brake_reason.is_some() {
+            self.vel = self.vel.min(target);
+        } else if target < self.vel {
+            self.vel = target;
+            self.braked(BrakeReason::Platoon, Some(ahead.id));
+        } else {
+            // Catch up no faster than from standing still to `SPEED_LIMIT` in a second
+            self.vel = target.min(self.vel + SPEED_LIMIT / FPS as f32);
+        }
+        true
+    }
+
+    /// ### is_granted
+    /// A car in a platoon crosses with it: the car in front of it in the platoon has entered
+    /// the intersection and keeps moving.
+    pub fn is_granted(&self, cars: &Perceived) -> bool {
+        self.car_ahead(cars)
+            .is_some_and(|ahead| ahead.index >= APPROACH_LENGTH && ahead.vel > 0.0)
+    }
+
+    // The car in front in the platoon, as seen in `cars`
+    fn car_ahead(&self, cars: &Perceived) -> Option<CarView> {
+        let id = self.ahead?;
+        cars.nearby(self.center_car(), PLATOON_DISTANCE + SECTOR_WIDTH)
+            .find(|c| c.id == id)
+    }
+
     /// ### center_car
     /// get the center point of a car
     pub fn center_car(&self) -> (f32, f32) {
-        let top = self.borders().top;
-        let right = self.borders().right;
-        let bottom = self.borders().bottom;
-        let left = self.borders().left;
-        (left + ((right - left) / 2.0), top + ((bottom - top) / 2.0))
+        self.view().center_car()
     }
 
     /// ### calculate_distance
@@ -172,10 +272,7 @@ impl Car {
     /// center points of both cars are used and then the distance formula:
     ///
     /// `sqrt(dx^2 + dy^2)`
-    pub fn calc_dist(&self, other: &Car) -> f32 {
-        let (x, y) = self.center_car();
-        let (x2, y2) = other.center_car();
-        let (dx, dy) = ((x - x2).abs(), (y - y2).abs());
-        (dx * dx + dy * dy).sqrt()
+    pub fn calc_dist(&self, other: &CarView) -> f32 {
+        self.view().calc_dist(other)
     }
 }
@@ -0,0 +1,462 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use rand::distributions::WeightedIndex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{DEMAND_RATE, DT};
+use crate::traffic::{Direction, Turning};
+
+/// ### Demand
+/// How cars arrive on every approach, in the order of `Direction::ALL`, and the seed of the
+/// random arrivals. Demand is written as JSON, for example:
+///
+/// ```json
+/// {
+///     "seed": 7,
+///     "approaches": [
+///         { "rate": 0.5, "turning": [1.0, 2.0, 1.0] },
+///         { "rate": 0.2 },
+///         { "rate": 0.1, "platoon": { "min": 3, "max": 6, "headway": 1.0 } },
+///         { "rate": 0.0 }
+///     ]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Demand {
+    #[serde(default)]
+    pub seed: u64,
+    pub approaches: [Approach; 4],
+    /// Rates over time that replace the `rate` of every approach.
+    #[serde(default)]
+    pub profile: Option<Profile>,
+}
+
+/// Arrivals on a single approach.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Approach {
+    /// Mean number of arrivals per second. Arrivals are a Poisson process, so the time between
+    /// two arrivals is exponentially distributed.
+    pub rate: f32,
+    /// Relative probability of turning left, going straight and turning right.
+    #[serde(default = "even")]
+    pub turning: [f32; 3],
+    /// Let every arrival be a platoon of cars instead of a single car.
+    #[serde(default)]
+    pub platoon: Option<Platoon>,
+}
+
+/// A burst of `min` to `max` cars arriving `headway` seconds apart. The time to the next
+/// arrival is counted from the last car of the platoon.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Platoon {
+    pub min: usize,
+    pub max: usize,
+    pub headway: f32,
+}
+
+fn even() -> [f32; 3] {
+    [1.0; 3]
+}
+
+impl Default for Demand {
+    fn default() -> Demand {
+        Demand::uniform(DEMAND_RATE)
+    }
+}
+
+impl Demand {
+    /// ### uniform
+    /// The same `rate` of single cars on every approach, turning every way equally often.
+    pub fn uniform(rate: f32) -> Demand {
+        let approach = Approach {
+            rate,
+            turning: even(),
+            platoon: None,
+        };
+        Demand {
+            seed: 0,
+            approaches: [(); 4].map(|_| approach.clone()),
+            profile: None,
+        }
+    }
+
+    /// ### from_od
+    /// Demand with the arrivals of every approach and their lanes following `od`.
+    pub fn from_od(od: &OdMatrix) -> Demand {
+        let approaches = Direction::ALL.map(|origin| {
+            let turning = od.turning(origin);
+            Approach {
+                rate: turning.iter().sum::<f32>() / 3600.0,
+                turning,
+                platoon: None,
+            }
+        });
+        Demand {
+            seed: 0,
+            approaches,
+            profile: None,
+        }
+    }
+
+    /// ### rate
+    /// Cars per second arriving on the approach with index `i` at `time` seconds.
+    pub fn rate(&self, i: usize, time: f64) -> f32 {
+        match &self.profile {
+            Some(profile) => profile.rate(i, time) / 3600.0,
+            None => self.approaches[i].rate,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Demand, DemandError> {
+        let json = fs::read_to_string(path).map_err(DemandError::Io)?;
+        Demand::parse(&json)
+    }
+
+    pub fn parse(json: &str) -> Result<Demand, DemandError> {
+        let demand: Demand = serde_json::from_str(json).map_err(DemandError::Parse)?;
+        for (i, approach) in demand.approaches.iter().enumerate() {
+            if let Some(platoon) = &approach.platoon {
+                platoon.validate().map_err(|reason| DemandError::Platoon {
+                    approach: i,
+                    reason,
+                })?;
+            }
+        }
+        Ok(demand)
+    }
+}
+
+impl Platoon {
+    /// ### validate
+    /// Check that a platoon has at least one car and can be as large as `min`, and that its
+    /// cars do not arrive before each other.
+    fn validate(&self) -> Result<(), String> {
+        if self.max < self.min.max(1) {
+            return Err(format!("max {} is less than {}", self.max, self.min.max(1)));
+        }
+        if self.headway < 0.0 {
+            return Err(format!("headway {} is negative", self.headway));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum DemandError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    Csv {
+        line: usize,
+        reason: String,
+    },
+    /// The platoon of the approach with index `approach` can not arrive.
+    Platoon {
+        approach: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for DemandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DemandError::Io(err) => write!(f, "could not read demand: {err}"),
+            DemandError::Parse(err) => write!(f, "invalid demand: {err}"),
+            DemandError::Csv { line, reason } => write!(f, "invalid CSV on line {line}: {reason}"),
+            DemandError::Platoon { approach, reason } => {
+                write!(f, "invalid platoon on approach {approach}: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DemandError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DemandError::Io(err) => Some(err),
+            DemandError::Parse(err) => Some(err),
+            DemandError::Csv { .. } | DemandError::Platoon { .. } => None,
+        }
+    }
+}
+
+/// ### Profile
+/// Arrival rates that change over simulated time, in vehicles per hour on every approach. The
+/// rate changes linearly between two points, and stays at the first and last point before and
+/// after them. Profiles are read from CSV with the time in seconds and a column per approach,
+/// for example a morning peak from the north:
+///
+/// ```csv
+/// time,north,east,south,west
+/// 0,300,300,300,300
+/// 600,1200,300,600,300
+/// 1200,300,300,300,300
+/// ```
+///
+/// Approaches without a column have no arrivals.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Profile {
+    /// Time in seconds and vehicles per hour of every approach, ordered by time.
+    pub points: Vec<(f32, [f32; 4])>,
+}
+
+impl Profile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Profile, DemandError> {
+        let csv = fs::read_to_string(path).map_err(DemandError::Io)?;
+        Profile::parse(&csv)
+    }
+
+    pub fn parse(csv: &str) -> Result<Profile, DemandError> {
+        let (lines, columns) = csv_header(csv)?;
+
+        let mut points: Vec<(f32, [f32; 4])> = Vec::new();
+        for (line, row) in lines {
+            let values = row
+                .split(',')
+                .map(|value| value.trim().parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|err| invalid(line, err.to_string()))?;
+            if values.len() != columns.len() + 1 {
+                let reason = format!("expected {} values", columns.len() + 1);
+                return Err(invalid(line, reason));
+            }
+            let time = values[0];
+            if points.last().is_some_and(|&(last, _)| time <= last) {
+                return Err(invalid(line, "time has to increase".to_owned()));
+            }
+            let mut rates = [0.0; 4];
+            for (&i, &rate) in columns.iter().zip(&values[1..]) {
+                rates[i] = rate;
+            }
+            points.push((time, rates));
+        }
+        if points.is_empty() {
+            return Err(invalid(1, "no rows".to_owned()));
+        }
+        Ok(Profile { points })
+    }
+
+    /// ### rate
+    /// Vehicles per hour arriving on the approach with index `i` at `time` seconds.
+    pub fn rate(&self, i: usize, time: f64) -> f32 {
+        let after = self.points.partition_point(|&(t, _)| (t as f64) <= time);
+        match (
+            self.points.get(after.wrapping_sub(1)),
+            self.points.get(after),
+        ) {
+            (Some(&(t0, r0)), Some(&(t1, r1))) => {
+                let f = ((time - t0 as f64) / (t1 - t0) as f64) as f32;
+                r0[i] + (r1[i] - r0[i]) * f
+            }
+            (Some(&(_, rates)), None) | (None, Some(&(_, rates))) => rates[i],
+            (None, None) => 0.0,
+        }
+    }
+}
+
+/// ### OdMatrix
+/// Origin–destination matrix: vehicles per hour from every approach to every exit, like the
+/// turning counts of a real junction. Read from CSV with a row per origin and a column per
+/// destination, for example:
+///
+/// ```csv
+/// origin,north,east,south,west
+/// north,0,120,400,80
+/// east,60,0,90,300
+/// south,350,100,0,150
+/// west,70,280,110,0
+/// ```
+///
+/// Missing rows and columns have no traffic. A car can not turn around, so traffic from an
+/// approach back to itself is invalid.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OdMatrix {
+    /// Vehicles per hour from the origin to the destination, indexed like `Direction::ALL`.
+    pub counts: [[f32; 4]; 4],
+}
+
+impl OdMatrix {
+    pub fn load(path: impl AsRef<Path>) -> Result<OdMatrix, DemandError> {
+        let csv = fs::read_to_string(path).map_err(DemandError::Io)?;
+        OdMatrix::parse(&csv)
+    }
+
+    pub fn parse(csv: &str) -> Result<OdMatrix, DemandError> {
+        let (lines, columns) = csv_header(csv)?;
+        let mut counts = [[0.0; 4]; 4];
+        for (line, row) in lines {
+            let mut values = row.split(',').map(str::trim);
+            let origin = values.next().unwrap_or_default();
+            let origin = direction(origin)
+                .ok_or_else(|| invalid(line, format!("unknown approach {origin:?}")))?;
+            let values = values
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|err| invalid(line, err.to_string()))?;
+            if values.len() != columns.len() {
+                let reason = format!("expected {} values", columns.len() + 1);
+                return Err(invalid(line, reason));
+            }
+            for (&destination, &count) in columns.iter().zip(&values) {
+                if destination == origin.index() && count != 0.0 {
+                    let reason = format!("cars from {origin:?} can not leave to {origin:?}");
+                    return Err(invalid(line, reason));
+                }
+                counts[origin.index()][destination] = count;
+            }
+        }
+        Ok(OdMatrix { counts })
+    }
+
+    /// ### turning
+    /// Vehicles per hour from `origin` turning left, going straight and turning right.
+    pub fn turning(&self, origin: Direction) -> [f32; 3] {
+        Turning::ALL.map(|turning| self.counts[origin.index()][origin.exit(&turning).index()])
+    }
+}
+
+fn invalid(line: usize, reason: String) -> DemandError {
+    DemandError::Csv { line, reason }
+}
+
+fn direction(name: &str) -> Option<Direction> {
+    Direction::ALL
+        .into_iter()
+        .find(|direction| format!("{direction:?}").eq_ignore_ascii_case(name))
+}
+
+// The non-empty lines after the header with their line numbers, and the index of the
+// `Direction` of every column after the first
+fn csv_header(csv: &str) -> Result<(impl Iterator<Item = (usize, &str)>, Vec<usize>), DemandError> {
+    let mut lines = csv
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+
+    let (line, header) = lines
+        .next()
+        .ok_or_else(|| invalid(1, "missing header".to_owned()))?;
+    let mut columns = Vec::new();
+    for name in header.split(',').skip(1).map(str::trim) {
+        let direction =
+            direction(name).ok_or_else(|| invalid(line, format!("unknown approach {name:?}")))?;
+        columns.push(direction.index());
+    }
+    Ok((lines, columns))
+}
+
+/// ### DemandGenerator
+/// Draws the arrivals of a `Demand` tick by tick. The same `Demand` and seed always give the
+/// same arrivals, independent of the rest of the simulation.
+///
+/// Arrivals are drawn by time rescaling: every approach draws an exponentially distributed
+/// amount of work with mean 1, and a car arrives once the rate integrated over time adds up to
+/// it. With a constant rate that is a Poisson process, and with a `Profile` the rate can change
+/// between arrivals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DemandGenerator {
+    demand: Demand,
+    rng: StdRng,
+    /// Time the arrivals were last drawn up to, in seconds.
+    time: f64,
+    /// Integrated rate left until the next arrival on every approach.
+    work: [f64; 4],
+    /// Number of cars of the current platoon still to arrive on every approach, and the time
+    /// the next of them arrives.
+    platoon: [(usize, f64); 4],
+}
+
+impl Default for DemandGenerator {
+    fn default() -> DemandGenerator {
+        DemandGenerator::new(Demand::default())
+    }
+}
+
+impl DemandGenerator {
+    pub fn new(demand: Demand) -> DemandGenerator {
+        let mut rng = StdRng::seed_from_u64(demand.seed);
+        let work = [(); 4].map(|_| exponential(&mut rng));
+        DemandGenerator {
+            demand,
+            rng,
+            time: 0.0,
+            work,
+            platoon: [(0, 0.0); 4],
+        }
+    }
+
+    pub fn demand(&self) -> &Demand {
+        &self.demand
+    }
+
+    /// ### arrivals
+    /// The cars arriving since the last call up to `tick`, in the order they arrive, and the
+    /// lane they arrive in. The rate is integrated per call, so it should be called every tick.
+    pub fn arrivals(&mut self, tick: u64) -> Vec<(Direction, Turning)> {
+        let now = (tick as f64 * DT as f64).max(self.time);
+        let last = std::mem::replace(&mut self.time, now);
+        let mut arrivals = Vec::new();
+        for (i, direction) in Direction::ALL.into_iter().enumerate() {
+            let mut time = last;
+            loop {
+                let (left, at) = self.platoon[i];
+                if left > 0 {
+                    if at > now {
+                        break;
+                    }
+                    time = at;
+                } else {
+                    // Mean rate between `time` and `now`, close enough for a single tick
+                    let rate = (self.demand.rate(i, time) + self.demand.rate(i, now)) as f64 / 2.0;
+                    let integrated = rate * (now - time).max(0.0);
+                    if rate <= 0.0 || self.work[i] > integrated {
+                        self.work[i] -= integrated;
+                        break;
+                    }
+                    time += self.work[i] / rate;
+                    self.work[i] = exponential(&mut self.rng);
+                }
+                arrivals.push((time, direction, self.turning(i)));
+                self.platoon[i] = self.next_in_platoon(i, time);
+            }
+        }
+        // Stable, so arrivals at the same time keep the order of `Direction::ALL`
+        arrivals.sort_by(|(a, ..), (b, ..)| a.total_cmp(b));
+        arrivals
+            .into_iter()
+            .map(|(_, direction, turning)| (direction, turning))
+            .collect()
+    }
+
+    fn turning(&mut self, i: usize) -> Turning {
+        match WeightedIndex::new(self.demand.approaches[i].turning) {
+            Ok(weights) => Turning::ALL[self.rng.sample(weights)],
+            Err(_) => Turning::ALL[self.rng.gen_range(0..Turning::ALL.len())],
+        }
+    }
+
+    // Cars of the platoon left after a car arrived at `time`, and when the next one arrives
+    fn next_in_platoon(&mut self, i: usize, time: f64) -> (usize, f64) {
+        let Some(platoon) = self.demand.approaches[i].platoon else {
+            return (0, 0.0);
+        };
+        let left = match self.platoon[i].0 {
+            0 => {
+                self.rng
+                    .gen_range(platoon.min.max(1)..=platoon.max.max(platoon.min.max(1)))
+                    - 1
+            }
+            left => left - 1,
+        };
+        (left, time + platoon.headway.max(0.0) as f64)
+    }
+}
+
+// Exponentially distributed with mean 1
+fn exponential(rng: &mut StdRng) -> f64 {
+    -(1.0 - rng.gen::<f64>()).ln()
+}
@@ -1,7 +1,13 @@
+use std::sync::OnceLock;
+
+use crate::config::SECTOR_WIDTH;
 use crate::traffic::car::Turning;
 use crate::traffic::{Direction, Moving};
 
-#[derive(Eq, Clone, Debug)]
+/// Number of sectors in a `Path` before it enters the intersection.
+pub const APPROACH_LENGTH: usize = 3;
+
+#[derive(Eq, Clone, Copy, Debug)]
 pub struct Sector {
     x: usize,
     y: usize,
@@ -36,6 +42,98 @@ pub struct Path {
 }
 
 impl Path {
+    /// ### get
+    /// Get the shared `Path` for a `Direction` and `Turning`.
+    /// There are only 12 different paths, so they are built once and every car borrows them.
+    pub fn get(direction: &Direction, turning: &Turning) -> &'static Path {
+        &Path::all()[direction.index() * 3 + turning.lane()]
+    }
+
+    /// ### all
+    /// Get every `Path` through the intersection.
+    pub fn all() -> &'static [Path] {
+        static PATHS: OnceLock<Vec<Path>> = OnceLock::new();
+        PATHS.get_or_init(|| {
+            Direction::ALL
+                .iter()
+                .flat_map(|direction| {
+                    Turning::ALL
+                        .iter()
+                        .map(|turning| Path::new(direction, turning))
+                })
+                .collect()
+        })
+    }
+
+    /// ### loops
+    /// Get every loop of sectors that cars can drive around by following the paths, each with
+    /// its sectors in the order cars drive through them. Cars that fill a loop, every car
+    /// waiting for the sector of the car in front of it, can never move again.
+    pub fn loops() -> &'static [Vec<Sector>] {
+        static LOOPS: OnceLock<Vec<Vec<Sector>>> = OnceLock::new();
+        LOOPS.get_or_init(|| {
+            // Every sector with the sectors cars drive on to from it
+            let mut next: Vec<(Sector, Vec<Sector>)> = Vec::new();
+            for pair in Path::all().iter().flat_map(|path| path.sectors.windows(2)) {
+                match next.iter_mut().find(|(sector, _)| *sector == pair[0]) {
+                    Some((_, to)) if to.contains(&pair[1]) => {}
+                    Some((_, to)) => to.push(pair[1]),
+                    None => next.push((pair[0], vec![pair[1]])),
+                }
+            }
+
+            let mut loops = Vec::new();
+            for start in 0..next.len() {
+                find_loops(&next, &mut vec![start], &mut loops);
+            }
+            loops
+        })
+    }
+
+    /// ### loops_from
+    /// Get the indices in `Path::loops` of the loops the path drives along from the sector at
+    /// `index` on. They are worked out once for every path of `Path::all` and every index, a
+    /// path that is not one of them drives along no loops.
+    pub fn loops_from(&self, index: usize) -> &'static [usize] {
+        static AHEAD: OnceLock<Vec<Vec<Vec<usize>>>> = OnceLock::new();
+        let ahead = AHEAD.get_or_init(|| {
+            Path::all()
+                .iter()
+                .map(|path| {
+                    (0..=path.sectors.len())
+                        .map(|index| loops_along(&path.sectors[index..]))
+                        .collect()
+                })
+                .collect()
+        });
+        Path::all()
+            .iter()
+            .position(|path| std::ptr::eq(path, self) || path == self)
+            .map_or(&[], |i| &ahead[i][index.min(self.sectors.len())])
+    }
+
+    /// ### approach_at
+    /// Find the approach lane at the point `(x, y)`: the sectors of a `Path` before it enters
+    /// the intersection. Returns the `Direction` and `Turning` of cars in that lane.
+    pub fn approach_at(x: f32, y: f32) -> Option<(Direction, Turning)> {
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+        let sector = Sector::new(
+            (x / SECTOR_WIDTH) as usize,
+            (y / SECTOR_WIDTH) as usize,
+            Moving::Up,
+        );
+
+        Direction::ALL.into_iter().find_map(|direction| {
+            Turning::ALL.into_iter().find_map(|turning| {
+                Path::get(&direction, &turning).sectors[..APPROACH_LENGTH]
+                    .contains(&sector)
+                    .then_some((direction, turning))
+            })
+        })
+    }
+
     pub fn new(direction: &Direction, turning: &Turning) -> Path {
         Path {
             sectors: match turning {
@@ -47,6 +145,41 @@ impl Path {
     }
 }
 
+/// ### loops_along
+/// Get the indices in `Path::loops` of the loops driven along by following `sectors`.
+fn loops_along(sectors: &[Sector]) -> Vec<usize> {
+    Path::loops()
+        .iter()
+        .enumerate()
+        .filter(|(_, of_loop)| {
+            sectors.windows(2).any(|pair| {
+                let at = of_loop.iter().position(|sector| *sector == pair[0]);
+                at.is_some_and(|i| of_loop[(i + 1) % of_loop.len()] == pair[1])
+            })
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// ### find_loops
+/// Follow `next` from the last sector of `walk` and record every walk that gets back to its
+/// first sector. Only sectors after the first one in `next` are walked through, so every loop
+/// is found once, from its first sector.
+fn find_loops(next: &[(Sector, Vec<Sector>)], walk: &mut Vec<usize>, loops: &mut Vec<Vec<Sector>>) {
+    let (start, last) = (walk[0], walk[walk.len() - 1]);
+    for sector in &next[last].1 {
+        match next.iter().position(|(s, _)| s == sector) {
+            Some(i) if i == start => loops.push(walk.iter().map(|&i| next[i].0).collect()),
+            Some(i) if i > start && !walk.contains(&i) => {
+                walk.push(i);
+                find_loops(next, walk, loops);
+                walk.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
 fn left_turn(direction: &Direction) -> Vec<Sector> {
     match direction {
         Direction::North => get_path(vec![
@@ -124,7 +257,7 @@ fn right_turn(direction: &Direction) -> Vec<Sector> {
 
 // Helper function to get all sectors in the path
 fn get_path(sectors: Vec<Sector>) -> Vec<Sector> {
-    let mut path = vec![sectors[0].clone()];
+    let mut path = vec![sectors[0]];
     let mut x: usize = sectors[0].x;
     let mut y: usize = sectors[0].y;
     while x != sectors[1].x || y != sectors[1].y {
@@ -141,7 +274,7 @@ fn get_path(sectors: Vec<Sector>) -> Vec<Sector> {
             y -= 1;
         }
 
-        path.push(Sector::new(x, y, sectors[0].clone().moving));
+        path.push(Sector::new(x, y, sectors[0].moving));
     }
     while x != sectors[2].x || y != sectors[2].y {
         if x < sectors[2].x {
@@ -156,7 +289,7 @@ fn get_path(sectors: Vec<Sector>) -> Vec<Sector> {
         if y > sectors[2].y {
             y -= 1;
         }
-        path.push(Sector::new(x, y, sectors[1].clone().moving));
+        path.push(Sector::new(x, y, sectors[1].moving));
     }
     path
 }
@@ -0,0 +1,291 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{SCAN_DISTANCE, SECTOR_WIDTH};
+use crate::traffic::car::{Car, CarView, Moving};
+use crate::traffic::path::Sector;
+use crate::traffic::spatial::SpatialIndex;
+
+/// Standard deviations at which the noise is cut off, so a perceived car is never further
+/// than this many times `Perception::position_noise` from its actual position on either axis.
+pub const NOISE_CUTOFF: f32 = 4.0;
+
+/// ### Perception
+/// How a car's sensors see the other cars. A car only decides on the cars within `range` and
+/// its field of view that are not hidden behind other cars, at a position and velocity off by
+/// random noise. The default perception sees every car exactly. Perceptions are read from
+/// JSON, for example a front-facing sensor with 3 units of noise:
+///
+/// ```json
+/// { "position_noise": 3.0, "velocity_noise": 0.05, "range": 300.0, "fov": 180.0, "occlusion": true }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Perception {
+    /// Standard deviation of the error of the perceived position, in world units.
+    pub position_noise: f32,
+    /// Standard deviation of the error of the perceived velocity, in the units of `Car::vel`.
+    pub velocity_noise: f32,
+    /// Furthest distance between the centers of two cars at which a car is seen, unlimited if
+    /// left out.
+    pub range: Option<f32>,
+    /// Field of view in degrees, centered on the direction the car is moving in.
+    pub fov: f32,
+    /// Hide cars behind other cars.
+    pub occlusion: bool,
+    pub seed: u64,
+}
+
+impl Default for Perception {
+    fn default() -> Perception {
+        Perception {
+            position_noise: 0.0,
+            velocity_noise: 0.0,
+            range: None,
+            fov: 360.0,
+            occlusion: false,
+            seed: 0,
+        }
+    }
+}
+
+impl Perception {
+    pub fn load(path: impl AsRef<Path>) -> Result<Perception, PerceptionError> {
+        let json = fs::read_to_string(path).map_err(PerceptionError::Io)?;
+        Perception::parse(&json)
+    }
+
+    pub fn parse(json: &str) -> Result<Perception, PerceptionError> {
+        serde_json::from_str(json).map_err(PerceptionError::Parse)
+    }
+
+    /// ### is_perfect
+    /// Whether every car is seen exactly where it is.
+    pub fn is_perfect(&self) -> bool {
+        self.position_noise == 0.0
+            && self.velocity_noise == 0.0
+            && self.range.is_none()
+            && self.fov >= 360.0
+            && !self.occlusion
+    }
+
+    /// ### perceive
+    /// Get the `cars` as `car` perceives them at `tick`.
+    pub fn perceive<'a>(&'a self, car: &Car, cars: &'a SpatialIndex, tick: u64) -> Perceived<'a> {
+        Perceived {
+            cars,
+            perception: self,
+            car: car.view(),
+            tick,
+            right_of_way: false,
+        }
+    }
+
+    /// ### detects
+    /// Whether `car` sees `other` within its range and field of view, and not hidden behind
+    /// one of the other `cars`.
+    fn detects(&self, car: &CarView, other: &CarView, cars: &SpatialIndex) -> bool {
+        let (x, y) = car.center_car();
+        let (dx, dy) = (other.center_car().0 - x, other.center_car().1 - y);
+        let distance = car.calc_dist(other);
+        if self.range.is_some_and(|range| distance > range) {
+            return false;
+        }
+
+        if self.fov < 360.0 && distance > 0.0 {
+            let (hx, hy) = heading(&car.moving);
+            let angle = ((dx * hx + dy * hy) / distance).clamp(-1.0, 1.0).acos();
+            if angle.to_degrees() > self.fov / 2.0 {
+                return false;
+            }
+        }
+
+        // Only a car closer than `other` can hide it
+        !self.occlusion
+            || !cars.nearby((x, y), distance).any(|c| {
+                c.id != car.id
+                    && c.id != other.id
+                    && car.calc_dist(c) < distance
+                    && distance_to_ray((x, y), (dx, dy), c.center_car()) < SECTOR_WIDTH / 2.0
+            })
+    }
+
+    /// ### noisy
+    /// `other` as `car` perceives it at `tick`.
+    ///
+    /// The noise only depends on the seed, the tick and the two cars, so every car can
+    /// perceive in any order.
+    fn noisy(&self, car: &CarView, other: &CarView, tick: u64) -> CarView {
+        let mut view = *other;
+        if self.position_noise == 0.0 && self.velocity_noise == 0.0 {
+            return view;
+        }
+
+        let mut rng = [tick, car.id as u64, other.id as u64]
+            .iter()
+            .fold(self.seed, |seed, &value| mix(seed ^ value));
+        view.x += gaussian(&mut rng) * self.position_noise;
+        view.y += gaussian(&mut rng) * self.position_noise;
+        view.vel = (view.vel + gaussian(&mut rng) * self.velocity_noise).max(0.0);
+        view
+    }
+}
+
+/// ### Perceived
+/// The cars of a `SpatialIndex` as one car perceives them, with the same queries as the
+/// index. Cars are filtered and made noisy as the queries reach them, so perceiving does not
+/// copy the index for every car.
+#[derive(Debug, Clone, Copy)]
+pub struct Perceived<'a> {
+    cars: &'a SpatialIndex,
+    perception: &'a Perception,
+    /// The car perceiving, as it was when it started deciding.
+    car: CarView,
+    tick: u64,
+    /// Leave out the cars holding for the car, unless they stand on the rest of its way.
+    right_of_way: bool,
+}
+
+impl<'a> Perceived<'a> {
+    /// ### with_right_of_way
+    /// Leave out the cars holding for the car that are not on the rest of its `Path`, as they
+    /// are out of its way.
+    pub fn with_right_of_way(self) -> Perceived<'a> {
+        Perceived {
+            right_of_way: true,
+            ..self
+        }
+    }
+
+    /// ### cars
+    /// All cars the car perceives.
+    pub fn cars(&self) -> impl Iterator<Item = CarView> + 'a {
+        self.perceived(self.cars.cars().iter())
+    }
+
+    /// ### nearby
+    /// Get every car whose perceived center could be within `distance` of the point `(x, y)`.
+    /// Callers still need to check the exact distance.
+    pub fn nearby(&self, point: (f32, f32), distance: f32) -> impl Iterator<Item = CarView> + 'a {
+        let distance = distance + NOISE_CUTOFF * self.perception.position_noise;
+        self.perceived(self.cars.nearby(point, distance))
+    }
+
+    /// ### in_sector
+    /// Get the cars the car perceives whose current `Sector` in their `Path` is `sector`.
+    pub fn in_sector(&self, sector: &Sector) -> impl Iterator<Item = CarView> + 'a {
+        self.perceived(self.cars.in_sector(sector))
+    }
+
+    /// ### in_middle
+    /// Get the cars the car perceives in one of the `MIDDLE_SECTORS` of the intersection.
+    pub fn in_middle(&self) -> impl Iterator<Item = CarView> + 'a {
+        self.perceived(self.cars.in_middle())
+    }
+
+    /// ### missed
+    /// Number of cars within `SCAN_DISTANCE` of the car it does not see.
+    pub fn missed(&self) -> usize {
+        if self.perception.is_perfect() {
+            return 0;
+        }
+        self.cars
+            .nearby(self.car.center_car(), SCAN_DISTANCE)
+            .filter(|c| c.id != self.car.id && self.car.calc_dist(c) <= SCAN_DISTANCE)
+            .filter(|c| !self.perception.detects(&self.car, c, self.cars))
+            .count()
+    }
+
+    fn perceived(
+        &self,
+        cars: impl Iterator<Item = &'a CarView> + 'a,
+    ) -> impl Iterator<Item = CarView> + 'a {
+        let perceived = *self;
+        cars.filter(move |c| c.id != perceived.car.id && perceived.keeps(c))
+            .map(move |c| {
+                perceived
+                    .perception
+                    .noisy(&perceived.car, c, perceived.tick)
+            })
+    }
+
+    fn keeps(&self, other: &CarView) -> bool {
+        let ahead = &self.car.path.sectors[self.car.index..];
+        (!self.right_of_way
+            || other.holding_for != Some(self.car.id)
+            || ahead.contains(&other.sector(0)))
+            && self.perception.detects(&self.car, other, self.cars)
+    }
+}
+
+#[derive(Debug)]
+pub enum PerceptionError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for PerceptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PerceptionError::Io(err) => write!(f, "could not read perception: {err}"),
+            PerceptionError::Parse(err) => write!(f, "invalid perception: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PerceptionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PerceptionError::Io(err) => Some(err),
+            PerceptionError::Parse(err) => Some(err),
+        }
+    }
+}
+
+// Helper functions for the geometry and noise
+fn heading(moving: &Moving) -> (f32, f32) {
+    match moving {
+        Moving::Up => (0.0, -1.0),
+        Moving::Right => (1.0, 0.0),
+        Moving::Down => (0.0, 1.0),
+        Moving::Left => (-1.0, 0.0),
+    }
+}
+
+/// Distance from `point` to the segment from `from` to `from + (dx, dy)`.
+fn distance_to_ray(from: (f32, f32), (dx, dy): (f32, f32), point: (f32, f32)) -> f32 {
+    let (px, py) = (point.0 - from.0, point.1 - from.1);
+    let length = dx * dx + dy * dy;
+    let t = if length > 0.0 {
+        ((px * dx + py * dy) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (ex, ey) = (px - t * dx, py - t * dy);
+    (ex * ex + ey * ey).sqrt()
+}
+
+/// A normally distributed value with mean 0 and standard deviation 1, cut off at
+/// `NOISE_CUTOFF` standard deviations.
+fn gaussian(rng: &mut u64) -> f32 {
+    let (u, v) = (uniform(rng).max(f32::EPSILON), uniform(rng));
+    let value = (-2.0 * u.ln()).sqrt() * (std::f32::consts::TAU * v).cos();
+    value.clamp(-NOISE_CUTOFF, NOISE_CUTOFF)
+}
+
+/// A uniformly distributed value from 0 to 1, moving `rng` on to the next state.
+fn uniform(rng: &mut u64) -> f32 {
+    *rng = mix(*rng);
+    (*rng >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// Scramble the bits of `seed` (SplitMix64), so nearby seeds give unrelated noise.
+fn mix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,280 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::DT;
+use crate::traffic::{Car, Direction, Model, Moving, State, Turning};
+
+/// ### Event
+/// One line of a replay file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Event {
+    Spawn(Spawn),
+    Tick(Frame),
+}
+
+/// A car entering the world.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Spawn {
+    pub tick: u64,
+    pub id: usize,
+    pub direction: Direction,
+    pub turning: Turning,
+    pub model: Model,
+}
+
+/// The state of every car at the end of a tick.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub tick: u64,
+    pub cars: Vec<CarFrame>,
+    /// Ids of the cars that collided during the tick.
+    pub collisions: Vec<usize>,
+    /// Ids of the cars that had a close call during the tick.
+    pub close_calls: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CarFrame {
+    pub id: usize,
+    pub x: f32,
+    pub y: f32,
+    pub vel: f32,
+    pub index: usize,
+    pub moving: Moving,
+    pub direction: Direction,
+    pub turning: Turning,
+    pub model: Model,
+}
+
+impl From<&Car> for CarFrame {
+    fn from(car: &Car) -> Self {
+        CarFrame {
+            id: car.id,
+            x: car.x,
+            y: car.y,
+            vel: car.vel,
+            index: car.index,
+            moving: car.moving,
+            direction: car.direction,
+            turning: car.turning,
+            model: car.model,
+        }
+    }
+}
+
+/// ### Recorder
+/// Writes the events recorded by a `State` as line-delimited JSON.
+pub struct Recorder<W: Write> {
+    writer: W,
+}
+
+impl Recorder<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Recorder::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W) -> Self {
+        Recorder { writer }
+    }
+
+    /// ### write
+    /// Write and remove all events recorded by `state` since the last call.
+    pub fn write(&mut self, state: &mut State) -> io::Result<()> {
+        for event in state.drain_events() {
+            serde_json::to_writer(&mut self.writer, &event)?;
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    /// A line that is not a valid `Event`, numbered from 1.
+    Parse {
+        line: usize,
+        source: serde_json::Error,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "could not read replay: {err}"),
+            ReplayError::Parse { line, source } => {
+                write!(f, "invalid event on line {line}: {source}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReplayError::Io(err) => Some(err),
+            ReplayError::Parse { source, .. } => Some(source),
+        }
+    }
+}
+
+/// ### read_events
+/// Read all events of a replay written by a `Recorder`.
+pub fn read_events(reader: impl BufRead) -> Result<Vec<Event>, ReplayError> {
+    let mut events = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(ReplayError::Io)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(&line).map_err(|source| ReplayError::Parse {
+            line: i + 1,
+            source,
+        })?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// ### Replay
+/// A recorded run played back frame by frame, without running any of the decision logic.
+/// The position can move forwards or backwards and jump to any tick of the recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    frames: Vec<Frame>,
+    position: usize,
+    /// Play the frames backwards.
+    pub reverse: bool,
+}
+
+impl Replay {
+    pub fn new(events: impl IntoIterator<Item = Event>) -> Replay {
+        let frames = events
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Tick(frame) => Some(frame),
+                Event::Spawn(_) => None,
+            })
+            .collect();
+        Replay {
+            frames,
+            position: 0,
+            reverse: false,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Replay, ReplayError> {
+        let file = File::open(path).map_err(ReplayError::Io)?;
+        Ok(Replay::new(read_events(io::BufReader::new(file))?))
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// ### frame
+    /// The frame at the current position, `None` if nothing was recorded.
+    pub fn frame(&self) -> Option<&Frame> {
+        self.frames.get(self.position)
+    }
+
+    /// ### cars
+    /// The cars of the current frame, to be drawn with `render_car`.
+    pub fn cars(&self) -> impl Iterator<Item = Car> + '_ {
+        self.frame()
+            .into_iter()
+            .flat_map(|frame| frame.cars.iter().map(Car::from))
+    }
+
+    /// ### time
+    /// Simulated time of the current frame in seconds.
+    pub fn time(&self) -> f32 {
+        self.frame().map_or(0.0, |frame| tick_time(frame.tick))
+    }
+
+    /// Simulated time of the last frame in seconds.
+    pub fn duration(&self) -> f32 {
+        self.frames
+            .last()
+            .map_or(0.0, |frame| tick_time(frame.tick))
+    }
+
+    /// ### advance
+    /// Move `ticks` frames forwards, or backwards when in `reverse`, stopping at either end.
+    pub fn advance(&mut self, ticks: u32) {
+        let last = self.frames.len().saturating_sub(1);
+        self.position = if self.reverse {
+            self.position.saturating_sub(ticks as usize)
+        } else {
+            (self.position + ticks as usize).min(last)
+        };
+    }
+
+    /// ### seek
+    /// Move to the last frame at or before `time` seconds, or the first frame if there is none.
+    pub fn seek(&mut self, time: f32) {
+        let tick = (time / DT).round() as u64;
+        self.position = self
+            .frames
+            .partition_point(|frame| frame.tick <= tick)
+            .saturating_sub(1);
+    }
+
+    /// ### next_incident
+    /// Move to the next frame with a collision or close call, in the direction of playback.
+    /// Returns `false` and stays put if there is none.
+    pub fn next_incident(&mut self) -> bool {
+        let is_incident =
+            |frame: &Frame| !frame.collisions.is_empty() || !frame.close_calls.is_empty();
+        let found = if self.reverse {
+            self.frames[..self.position].iter().rposition(is_incident)
+        } else {
+            self.frames
+                .iter()
+                .skip(self.position + 1)
+                .position(is_incident)
+                .map(|i| self.position + 1 + i)
+        };
+        if let Some(position) = found {
+            self.position = position;
+        }
+        found.is_some()
+    }
+
+    /// ### incidents
+    /// Time in seconds of every frame with a collision or close call, and whether it had a
+    /// collision.
+    pub fn incidents(&self) -> impl Iterator<Item = (f32, bool)> + '_ {
+        self.frames
+            .iter()
+            .filter(|frame| !frame.collisions.is_empty() || !frame.close_calls.is_empty())
+            .map(|frame| (tick_time(frame.tick), !frame.collisions.is_empty()))
+    }
+}
+
+impl From<&CarFrame> for Car {
+    fn from(frame: &CarFrame) -> Self {
+        let mut car = Car::new(frame.direction, frame.turning, frame.id);
+        car.x = frame.x;
+        car.y = frame.y;
+        car.vel = frame.vel;
+        car.index = frame.index;
+        car.moving = frame.moving;
+        car.model = frame.model;
+        car
+    }
+}
+
+fn tick_time(tick: u64) -> f32 {
+    tick as f32 * DT
+}
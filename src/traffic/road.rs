@@ -1,11 +1,16 @@
-use rand::prelude::IteratorRandom;
+use std::collections::VecDeque;
 
+use macroquad::rand::ChooseRandom;
+
+use crate::config::{FPS, MAX_PLATOON_SIZE, MAX_VELOCITY, PLATOON_DISTANCE, SECTOR_WIDTH};
 use crate::traffic::car::Car;
 use crate::traffic::{Direction, Statistics, Turning};
 #[derive(PartialEq, Debug, Clone)]
 pub struct Road {
     direction: Direction,
     pub cars: [Vec<Car>; 3],
+    /// Cars waiting to enter each lane, in the order they arrived.
+    queues: [VecDeque<Car>; 3],
 }
 
 impl Road {
@@ -13,42 +18,53 @@ impl Road {
         Road {
             direction,
             cars: [Vec::new(), Vec::new(), Vec::new()],
+            queues: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
         }
     }
 
     pub fn add_car(&mut self, car: Car) {
-        match car.turning {
-            Turning::Left => self.cars[0].push(car.clone()),
-            Turning::Straight => self.cars[1].push(car.clone()),
-            Turning::Right => self.cars[2].push(car.clone()),
-        }
+        self.cars[car.turning.lane()].push(car);
+    }
+
+    /// ### enqueue
+    /// Let a car wait to enter the lane for its turning.
+    pub fn enqueue(&mut self, car: Car) {
+        self.queues[car.turning.lane()].push_back(car);
+    }
+
+    /// ### dequeue
+    /// Take the car that has waited longest to enter the lane for `turning`.
+    pub fn dequeue(&mut self, turning: &Turning) -> Option<Car> {
+        self.queues[turning.lane()].pop_front()
+    }
+
+    /// Number of cars waiting to enter the lane for `turning`.
+    pub fn queued(&self, turning: &Turning) -> usize {
+        self.queues[turning.lane()].len()
     }
 
-    pub fn get_available_path(&self) -> Option<Turning> {
+    pub fn is_queued(&self, id: usize) -> bool {
+        self.queues.iter().flatten().any(|car| car.id == id)
+    }
+
+    /// ### get_available_path
+    /// Pick an available lane, weighted by the relative probability in `weights` of turning
+    /// left, going straight and turning right. Falls back to any available lane when all of
+    /// them have a weight of 0.
+    pub fn get_available_path(&self, weights: [f32; 3]) -> Option<Turning> {
         let lanes = self.available_lanes();
-        let mut paths = Vec::new();
-
-        for (i, available) in lanes.into_iter().enumerate() {
-            match i {
-                0 => {
-                    if available {
-                        paths.push(Turning::Left);
-                    }
-                }
-                1 => {
-                    if available {
-                        paths.push(Turning::Straight);
-                    }
-                }
-                _ => {
-                    if available {
-                        paths.push(Turning::Right);
-                    }
-                }
-            }
-        }
+        let paths: Vec<Turning> = Turning::ALL
+            .into_iter()
+            .filter(|turning| lanes[turning.lane()])
+            .collect();
 
-        paths.into_iter().choose(&mut rand::thread_rng())
+        Turning::choose(&paths, weights).or_else(|| paths.choose().copied())
+    }
+
+    /// ### is_available
+    /// Check if a new car can enter the lane for `turning`.
+    pub fn is_available(&self, turning: &Turning) -> bool {
+        self.available_lanes()[turning.lane()]
     }
 
     fn available_lanes(&self) -> [bool; 3] {
@@ -84,12 +100,54 @@ impl Road {
         available
     }
 
+    /// ### form_platoons
+    /// Group consecutive cars in the left and straight lanes into platoons of up to
+    /// `MAX_PLATOON_SIZE`, where every car is at most `PLATOON_DISTANCE` behind the car in
+    /// front of it. Records the size and time gap of every platoon in `stats`.
+    pub fn form_platoons(&mut self, enabled: bool, stats: &mut Statistics) {
+        for lane in self.cars.iter_mut() {
+            for car in lane.iter_mut() {
+                (car.platoon, car.ahead) = (None, None);
+            }
+        }
+        if !enabled {
+            return;
+        }
+
+        for lane in self.cars.iter_mut().take(2) {
+            let mut leader = 0;
+            for i in 1..lane.len() {
+                let ahead = lane[i - 1].view();
+                let distance = lane[i].calc_dist(&ahead);
+                if i - leader >= MAX_PLATOON_SIZE || distance > PLATOON_DISTANCE {
+                    leader = i;
+                    continue;
+                }
+                let id = lane[leader].id;
+                lane[leader].platoon = Some(id);
+                let car = &mut lane[i];
+                (car.platoon, car.ahead) = (Some(id), Some(ahead.id));
+
+                if car.vel > 0.0 {
+                    let gap = (distance - SECTOR_WIDTH).max(0.0);
+                    stats.set_platoon_gap(gap / (car.vel * MAX_VELOCITY * FPS as f32));
+                }
+            }
+        }
+
+        for lane in self.cars.iter().take(2) {
+            for car in lane.iter().filter(|car| car.platoon == Some(car.id)) {
+                stats.set_platoon(lane.iter().filter(|c| c.platoon == Some(car.id)).count());
+            }
+        }
+    }
+
     // Add time for all cars that reached their destination and then remove from vector.
-    pub fn cleanup_cars(&mut self, stats: &mut Statistics) {
+    pub fn cleanup_cars(&mut self, stats: &mut Statistics, tick: u64) {
         self.cars.iter().for_each(|cars| {
             cars.iter()
                 .filter(|car| car.is_done())
-                .for_each(|car| car.add_time(stats))
+                .for_each(|car| car.add_time(stats, tick))
         });
 
         self.cars[0].retain(|car| !car.is_done());
@@ -0,0 +1,349 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use macroquad::rand::gen_range;
+use serde::{Deserialize, Serialize};
+
+use crate::config::DT;
+use crate::traffic::perception::Perception;
+use crate::traffic::v2v::{Channel, MessageBus};
+use crate::traffic::{Direction, State, Turning};
+
+/// ### Scenario
+/// A scripted run of the simulation: which cars to spawn when, how long to run and what
+/// has to hold at the end. Scenarios are written as JSON, for example:
+///
+/// ```json
+/// {
+///     "name": "left turns",
+///     "ticks": 1200,
+///     "spawns": [
+///         { "tick": 0, "direction": "North", "turning": "Left" },
+///         { "tick": 0, "direction": "South" }
+///     ],
+///     "expect": ["NoCollisions", { "ExitsBefore": { "car": 0, "tick": 900 } }]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: String,
+    /// Number of ticks to run for.
+    pub ticks: u64,
+    #[serde(default)]
+    pub spawns: Vec<ScriptedSpawn>,
+    #[serde(default)]
+    pub expect: Vec<Expect>,
+    /// Let consecutive cars in a lane drive as platoons.
+    #[serde(default)]
+    pub platooning: bool,
+    /// The channel the cars broadcast their state over.
+    #[serde(default)]
+    pub channel: Channel,
+    /// How the cars see each other.
+    #[serde(default)]
+    pub perception: Perception,
+}
+
+/// A car to spawn at `tick`. A random direction or turning is picked when it is left out.
+/// With a `count` the spawn is repeated that many times, `every` ticks apart.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScriptedSpawn {
+    pub tick: u64,
+    #[serde(default)]
+    pub direction: Option<Direction>,
+    #[serde(default)]
+    pub turning: Option<Turning>,
+    #[serde(default = "one")]
+    pub count: usize,
+    #[serde(default)]
+    pub every: u64,
+}
+
+fn one() -> usize {
+    1
+}
+
+/// What has to hold at the end of a `Scenario`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Expect {
+    /// No car came within `COLLISION_DISTANCE` of another car during any tick.
+    NoCollisions,
+    /// No car came within `CLOSE_CALL_DISTANCE` of another car during any tick.
+    NoCloseCalls,
+    /// The `Watchdog` never had to break a deadlock.
+    NoDeadlocks,
+    /// Every car has left the world.
+    AllExit,
+    /// The `car`th car of the scenario, counting in the order they are due, has left the
+    /// world before `tick`.
+    ExitsBefore { car: usize, tick: u64 },
+    /// No car took more than `ticks` from the tick it was due until it exited, counting the
+    /// cars still in the world or waiting to spawn.
+    MaxDelay { ticks: u64 },
+    /// No car stood still for more than `ticks`, counting the cars still in it.
+    MaxWait { ticks: u64 },
+}
+
+/// An `Expect` that did not hold.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Failure {
+    /// Number of collision events, see `Statistics::collision_events`.
+    Collisions(u32),
+    /// Number of close call events, see `Statistics::close_call_events`.
+    CloseCalls(u32),
+    /// Number of deadlocks, see `Statistics::deadlocks`.
+    Deadlocks(usize),
+    Remaining(usize),
+    /// The car exited at the given tick, or not at all.
+    LateExit {
+        car: usize,
+        tick: u64,
+        exited: Option<u64>,
+    },
+    NotSpawned(usize),
+    /// The car took `ticks` since it was due, more than `max`.
+    Delayed {
+        car: usize,
+        ticks: u64,
+        max: u64,
+    },
+    /// A car stood still for `ticks`, more than `max`.
+    Waited {
+        ticks: u64,
+        max: u64,
+    },
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Collisions(n) => write!(f, "{n} collision events"),
+            Failure::CloseCalls(n) => write!(f, "{n} close call events"),
+            Failure::Deadlocks(n) => write!(f, "{n} deadlocks"),
+            Failure::Remaining(n) => write!(f, "{n} cars did not exit"),
+            Failure::LateExit {
+                car,
+                tick,
+                exited: Some(exited),
+            } => write!(
+                f,
+                "car {car} exited at tick {exited}, expected before {tick}"
+            ),
+            Failure::LateExit { car, tick, .. } => {
+                write!(f, "car {car} did not exit, expected before tick {tick}")
+            }
+            Failure::NotSpawned(car) => write!(f, "car {car} was never spawned"),
+            Failure::Delayed { car, ticks, max } => {
+                write!(f, "car {car} took {ticks} ticks, expected at most {max}")
+            }
+            Failure::Waited { ticks, max } => {
+                write!(f, "a car waited {ticks} ticks, expected at most {max}")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Io(err) => write!(f, "could not read scenario: {err}"),
+            ScenarioError::Parse(err) => write!(f, "invalid scenario: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScenarioError::Io(err) => Some(err),
+            ScenarioError::Parse(err) => Some(err),
+        }
+    }
+}
+
+impl Scenario {
+    pub fn load(path: impl AsRef<Path>) -> Result<Scenario, ScenarioError> {
+        let json = fs::read_to_string(path).map_err(ScenarioError::Io)?;
+        Scenario::parse(&json)
+    }
+
+    pub fn parse(json: &str) -> Result<Scenario, ScenarioError> {
+        serde_json::from_str(json).map_err(ScenarioError::Parse)
+    }
+
+    /// ### run
+    /// Run the scenario from an empty `State` and check its expectations.
+    pub fn run(&self) -> Result<State, Vec<Failure>> {
+        let mut state = State::new();
+        state.platooning = self.platooning;
+        state.bus = MessageBus::new(self.channel);
+        state.perception = self.perception;
+        let mut runner = ScenarioRunner::new(self.clone());
+        while !runner.is_finished(&state) {
+            runner.update(&mut state);
+        }
+        match runner.check(&state) {
+            failures if failures.is_empty() => Ok(state),
+            failures => Err(failures),
+        }
+    }
+}
+
+/// ### ScenarioRunner
+/// Spawns the cars of a `Scenario` into a `State` as it updates and tracks when they exit.
+///
+/// Repeated spawns are counted once for every car, and a spawn whose lane is taken is retried
+/// every tick until the lane is free, so the `n`th spawn is always the same car.
+#[derive(Debug, Clone)]
+pub struct ScenarioRunner {
+    scenario: Scenario,
+    /// The tick, direction and turning of every car, with the random ones picked.
+    spawns: Vec<(u64, Direction, Turning)>,
+    /// Id of the car of every spawn, once it is spawned.
+    ids: Vec<Option<usize>>,
+    exits: Vec<Option<u64>>,
+    /// Spawn of every car that is still in the world, by id.
+    driving: HashMap<usize, usize>,
+}
+
+impl ScenarioRunner {
+    pub fn new(scenario: Scenario) -> ScenarioRunner {
+        let mut spawns: Vec<_> = scenario
+            .spawns
+            .iter()
+            .flat_map(|spawn| (0..spawn.count as u64).map(move |i| (spawn, i)))
+            .map(|(spawn, i)| {
+                (
+                    spawn.tick + i * spawn.every,
+                    spawn
+                        .direction
+                        .unwrap_or_else(|| Direction::ALL[gen_range(0, Direction::ALL.len())]),
+                    spawn
+                        .turning
+                        .unwrap_or_else(|| Turning::ALL[gen_range(0, Turning::ALL.len())]),
+                )
+            })
+            .collect();
+        spawns.sort_by_key(|&(tick, ..)| tick);
+        let cars = spawns.len();
+        ScenarioRunner {
+            scenario,
+            spawns,
+            ids: vec![None; cars],
+            exits: vec![None; cars],
+            driving: HashMap::new(),
+        }
+    }
+
+    pub fn scenario(&self) -> &Scenario {
+        &self.scenario
+    }
+
+    pub fn is_finished(&self, state: &State) -> bool {
+        state.tick >= self.scenario.ticks
+    }
+
+    /// ### spawn
+    /// Spawn the cars that are due at the current tick of `state`.
+    pub fn spawn(&mut self, state: &mut State) {
+        for (i, &(tick, direction, turning)) in self.spawns.iter().enumerate() {
+            if tick > state.tick {
+                break;
+            }
+            if self.ids[i].is_some() {
+                continue;
+            }
+            // Waiting here instead of in an entry queue keeps the rejected spawns of a
+            // scenario out of its statistics
+            if !state.is_lane_free(direction, turning) {
+                continue;
+            }
+            if let Ok(id) = state.add_car_turning(direction, turning) {
+                self.ids[i] = Some(id);
+                self.driving.insert(id, i);
+            }
+        }
+    }
+
+    /// ### update
+    /// Spawn the cars that are due and advance `state` by one tick.
+    pub fn update(&mut self, state: &mut State) {
+        self.spawn(state);
+        state.update();
+
+        if self.driving.is_empty() {
+            return;
+        }
+        let present: HashSet<usize> = state.cars().map(|car| car.id).collect();
+        let exits = &mut self.exits;
+        self.driving.retain(|id, &mut i| {
+            if present.contains(id) {
+                return true;
+            }
+            exits[i] = Some(state.tick);
+            false
+        });
+    }
+
+    /// ### check
+    /// The expectations of the scenario that do not hold for `state`.
+    pub fn check(&self, state: &State) -> Vec<Failure> {
+        self.scenario
+            .expect
+            .iter()
+            .filter_map(|expect| match *expect {
+                Expect::NoCollisions if state.stats.collision_events() > 0 => {
+                    Some(Failure::Collisions(state.stats.collision_events()))
+                }
+                Expect::NoCloseCalls if state.stats.close_call_events() > 0 => {
+                    Some(Failure::CloseCalls(state.stats.close_call_events()))
+                }
+                Expect::NoDeadlocks if !state.stats.deadlocks().is_empty() => {
+                    Some(Failure::Deadlocks(state.stats.deadlocks().len()))
+                }
+                Expect::AllExit if state.car_count() > 0 => {
+                    Some(Failure::Remaining(state.car_count()))
+                }
+                Expect::ExitsBefore { car, tick } => match self.ids.get(car).copied().flatten() {
+                    None => Some(Failure::NotSpawned(car)),
+                    Some(_) => match self.exits[car] {
+                        Some(exited) if exited < tick => None,
+                        exited => Some(Failure::LateExit { car, tick, exited }),
+                    },
+                },
+                Expect::MaxDelay { ticks: max } => self
+                    .delays(state)
+                    .filter(|&(_, ticks)| ticks > max)
+                    .max_by_key(|&(_, ticks)| ticks)
+                    .map(|(car, ticks)| Failure::Delayed { car, ticks, max }),
+                Expect::MaxWait { ticks: max } => {
+                    let exited = (state.stats.max_wait() / DT).round() as u64;
+                    let ticks = state.cars().map(|car| car.waited).fold(exited, u64::max);
+                    (ticks > max).then_some(Failure::Waited { ticks, max })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// ### delays
+    /// Number of ticks every car spent since it was due, up to now for the cars still in the
+    /// world or waiting to spawn. The ticks a spawn is retried count as delay.
+    pub fn delays<'a>(&'a self, state: &'a State) -> impl Iterator<Item = (usize, u64)> + 'a {
+        self.spawns
+            .iter()
+            .zip(&self.exits)
+            .enumerate()
+            .filter(move |(_, ((tick, ..), _))| *tick <= state.tick)
+            .map(move |(car, ((tick, ..), exit))| (car, exit.unwrap_or(state.tick) - tick))
+    }
+}
@@ -0,0 +1,110 @@
+use crate::config::SECTOR_WIDTH;
+use crate::traffic::car::CarView;
+use crate::traffic::path::Sector;
+
+/// Number of sectors along each side of the grid.
+pub const GRID_SIZE: usize = 12;
+
+/// The four sectors in the middle of the intersection.
+pub const MIDDLE_SECTORS: [(usize, usize); 4] = [(5, 5), (5, 6), (6, 5), (6, 6)];
+
+/// ### SpatialIndex
+/// Buckets the cars of a tick into the 12x12 grid of sectors, once by the sector their
+/// center point is in and once by the current `Sector` of their `Path`.
+/// Neighbour queries then only look at the buckets around a car instead of every car.
+///
+/// The index keeps its buffers between ticks, so `rebuild` does not allocate once the
+/// buffers have grown to the number of cars.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpatialIndex {
+    cars: Vec<CarView>,
+    by_position: Vec<Vec<usize>>,
+    by_sector: Vec<Vec<usize>>,
+}
+
+impl SpatialIndex {
+    pub fn new(cars: impl IntoIterator<Item = CarView>) -> SpatialIndex {
+        let mut index = SpatialIndex {
+            cars: Vec::new(),
+            by_position: vec![Vec::new(); GRID_SIZE * GRID_SIZE],
+            by_sector: vec![Vec::new(); GRID_SIZE * GRID_SIZE],
+        };
+        index.rebuild(cars);
+        index
+    }
+
+    /// ### rebuild
+    /// Replace the cars in the index, reusing the buffers of the previous tick.
+    pub fn rebuild(&mut self, cars: impl IntoIterator<Item = CarView>) {
+        self.cars.clear();
+        self.cars.extend(cars);
+
+        self.by_position.iter_mut().for_each(Vec::clear);
+        self.by_sector.iter_mut().for_each(Vec::clear);
+
+        for (i, car) in self.cars.iter().enumerate() {
+            let (x, y) = car.center_car();
+            self.by_position[bucket(cell(x), cell(y))].push(i);
+
+            let sector = car.sector(0);
+            self.by_sector[bucket(sector.get_x(), sector.get_y())].push(i);
+        }
+    }
+
+    /// ### cars
+    /// All cars in the index, in the order they were inserted.
+    pub fn cars(&self) -> &[CarView] {
+        &self.cars
+    }
+
+    /// ### nearby
+    /// Get every car whose center could be within `distance` of the point `(x, y)`.
+    /// Callers still need to check the exact distance.
+    pub fn nearby(&self, (x, y): (f32, f32), distance: f32) -> impl Iterator<Item = &CarView> {
+        let (min_x, max_x) = (cell(x - distance), cell(x + distance));
+        let (min_y, max_y) = (cell(y - distance), cell(y + distance));
+
+        (min_y..=max_y)
+            .flat_map(move |y| (min_x..=max_x).map(move |x| bucket(x, y)))
+            .flat_map(|b| self.by_position[b].iter())
+            .map(|&i| &self.cars[i])
+    }
+
+    /// ### in_sector
+    /// Get the cars whose current `Sector` in their `Path` is `sector`.
+    pub fn in_sector(&self, sector: &Sector) -> impl Iterator<Item = &CarView> {
+        self.at(sector.get_x(), sector.get_y())
+    }
+
+    /// ### at
+    /// Get the cars whose current `Sector` in their `Path` is at `(x, y)`.
+    pub fn at(&self, x: usize, y: usize) -> impl Iterator<Item = &CarView> {
+        let cars: &[usize] = if x < GRID_SIZE && y < GRID_SIZE {
+            &self.by_sector[bucket(x, y)]
+        } else {
+            &[]
+        };
+        cars.iter().map(|&i| &self.cars[i])
+    }
+
+    /// ### in_middle
+    /// Get the cars currently in one of the `MIDDLE_SECTORS` of the intersection.
+    pub fn in_middle(&self) -> impl Iterator<Item = &CarView> {
+        MIDDLE_SECTORS.iter().flat_map(|&(x, y)| self.at(x, y))
+    }
+}
+
+impl Default for SpatialIndex {
+    fn default() -> Self {
+        Self::new([])
+    }
+}
+
+// Helper functions to map coordinates to the grid
+fn cell(coord: f32) -> usize {
+    ((coord / SECTOR_WIDTH).floor().max(0.0) as usize).min(GRID_SIZE - 1)
+}
+
+fn bucket(x: usize, y: usize) -> usize {
+    y * GRID_SIZE + x
+}
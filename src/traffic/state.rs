@@ -1,12 +1,21 @@
-use crate::config::{CLOSE_CALL_DISTANCE, COLLISION_DISTANCE, MARGIN, SECTOR_WIDTH};
+use crate::config::{CLOSE_CALL_DISTANCE, COLLISION_DISTANCE, DT, FPS, MAX_WAIT};
+use std::fmt;
+
 use macroquad::rand::gen_range;
+use serde::{Deserialize, Serialize};
 
-use crate::traffic::car::Car;
+use crate::traffic::car::{BrakeReason, Car};
+use crate::traffic::demand::DemandGenerator;
+use crate::traffic::perception::Perception;
+use crate::traffic::replay::{Event, Frame, Spawn};
 use crate::traffic::road::Road;
+use crate::traffic::spatial::SpatialIndex;
 use crate::traffic::statistics::*;
+use crate::traffic::v2v::MessageBus;
+use crate::traffic::watchdog::Watchdog;
 use crate::traffic::Turning;
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Direction {
     North,
     East,
@@ -14,13 +23,123 @@ pub enum Direction {
     West,
 }
 
-#[derive(PartialEq, Debug)]
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
+
+    /// ### index
+    /// Index of the `Road` for this `Direction` in `State.roads`.
+    pub fn index(&self) -> usize {
+        match self {
+            Direction::North => 0,
+            Direction::East => 1,
+            Direction::South => 2,
+            Direction::West => 3,
+        }
+    }
+
+    /// ### exit
+    /// Side of the world a car from this `Direction` leaves through when `turning`.
+    pub fn exit(&self, turning: &Turning) -> Direction {
+        let quarter_turns = match turning {
+            Turning::Left => 1,
+            Turning::Straight => 2,
+            Turning::Right => 3,
+        };
+        Direction::ALL[(self.index() + quarter_turns) % Direction::ALL.len()]
+    }
+
+    /// ### turning_to
+    /// Turning that takes a car from this `Direction` out through `exit`, or `None` for a
+    /// U-turn.
+    pub fn turning_to(&self, exit: Direction) -> Option<Turning> {
+        Turning::ALL
+            .into_iter()
+            .find(|turning| self.exit(turning) == exit)
+    }
+}
+
+/// ### SpawnError
+/// Why a car could not be added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// Every lane of the road is busy.
+    RoadBusy(Direction),
+    /// The lane is busy and there is no entry queue.
+    LaneBusy(Direction, Turning),
+    /// The lane is busy and its entry queue is full.
+    QueueFull(Direction, Turning),
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpawnError::RoadBusy(direction) => write!(f, "every lane from {direction:?} is busy"),
+            SpawnError::LaneBusy(direction, turning) => {
+                write!(f, "the {turning:?} lane from {direction:?} is busy")
+            }
+            SpawnError::QueueFull(direction, turning) => {
+                write!(
+                    f,
+                    "the queue for the {turning:?} lane from {direction:?} is full"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {}
+
+#[derive(PartialEq, Debug, Clone)]
 pub struct State {
     pub roads: [Road; 4],
     pub stats: Statistics,
     pub show_final_statistics: bool,
+    pub show_hud: bool,
+    pub show_debug: bool,
+    /// Draw the roads with the background texture instead of from the paths.
+    pub textured_roads: bool,
+    /// Id of the car shown in the inspector.
+    pub inspected: Option<usize>,
+    /// Spawn the cars arriving from `demand`.
     pub random: bool,
+    pub demand: DemandGenerator,
     pub total_cars: usize,
+    /// Number of ticks of `DT` simulated so far.
+    pub tick: u64,
+    /// Number of ticks a car can stand still before cars from other directions give way to it.
+    pub max_wait: u64,
+    /// Number of cars that can wait to enter each lane while it is busy, 0 to reject them.
+    pub entry_queue: usize,
+    /// Let consecutive cars in a lane drive as platoons.
+    pub platooning: bool,
+    /// Carries the state the cars broadcast to each other.
+    pub bus: MessageBus,
+    /// How the cars see the other cars they decide on.
+    pub perception: Perception,
+    /// Read-only snapshot of the previous tick, the actual state of the cars.
+    front: SpatialIndex,
+    /// The other cars as last received over the `bus`, that all cars decide against.
+    seen: SpatialIndex,
+    outcomes: Vec<Outcome>,
+    watchdog: Watchdog,
+    /// Events recorded for a replay since they were last drained, if recording.
+    events: Option<Vec<Event>>,
+}
+
+/// What happened to a single car during a tick, to be recorded in `Statistics`.
+#[derive(PartialEq, Debug, Clone)]
+struct Outcome {
+    id: usize,
+    collision: bool,
+    close_call: bool,
+    velocity: Option<f32>,
+    /// Cars within `SCAN_DISTANCE` the car did not perceive.
+    missed: usize,
 }
 
 impl State {
@@ -34,88 +153,392 @@ impl State {
             ],
             stats: Statistics::default(),
             random: false,
+            demand: DemandGenerator::default(),
             total_cars: 0,
+            tick: 0,
             show_final_statistics: false,
+            show_hud: false,
+            show_debug: false,
+            textured_roads: false,
+            inspected: None,
+            front: SpatialIndex::default(),
+            seen: SpatialIndex::default(),
+            bus: MessageBus::default(),
+            perception: Perception::default(),
+            max_wait: MAX_WAIT,
+            entry_queue: 0,
+            platooning: false,
+            outcomes: Vec::new(),
+            watchdog: Watchdog::new(),
+            events: None,
         }
     }
 
+    /// ### update
+    /// Advance the world by one tick of `DT` simulated seconds.
+    ///
+    /// The world is double-buffered: every car decides and moves against the `seen` buffer,
+    /// the other cars as received over the `bus` from the previous tick, and writes only to
+    /// itself in `roads`. Every car sees `seen` through its `perception`. Collisions are
+    /// detected against `front`, the actual previous tick.
+    /// With the `parallel` feature the cars are stepped in parallel, with results identical
+    /// to `update_sequential`.
     pub fn update(&mut self) {
-        let all_cars = self.get_all_cars();
-
-        self.roads.iter_mut().for_each(|road| {
-            // Cleanup and statistics logic
-            road.cleanup_cars(&mut self.stats);
-
-            // Iterating over each lane's cars
-            road.cars.iter_mut().for_each(|cars| {
-                cars.iter_mut().for_each(|car| {
-                    if detect_collision(car, &all_cars) {
-                        self.stats.set_collisions()
-                    } else if detect_close_call(car, &all_cars) {
-                        self.stats.set_close_calls();
-                    }
-
-                    if detect_deadlock(&all_cars, car) {
-                        car.stop();
-                        return;
-                    }
-                    self.stats.set_velocity(car.vel);
-                    car.move_car(&all_cars);
-                });
-            });
-        });
+        self.spawn_arrivals();
+
+        #[cfg(feature = "parallel")]
+        self.update_parallel();
+
+        #[cfg(not(feature = "parallel"))]
+        self.update_sequential();
     }
-    pub fn add_car(&mut self, direction: Direction) {
-        if self.get_all_cars().iter().filter(|c| c.vel == 0.0).count() >= 8 {
-            return;
+
+    pub fn update_sequential(&mut self) {
+        self.swap_buffers();
+        self.tick += 1;
+
+        let world = World {
+            front: &self.front,
+            seen: &self.seen,
+            perception: &self.perception,
+            tick: self.tick,
+            max_wait: self.max_wait,
+        };
+        self.outcomes.clear();
+        self.outcomes.extend(
+            self.roads
+                .iter_mut()
+                .flat_map(|road| road.cars.iter_mut().flatten())
+                .map(|car| step(car, &world)),
+        );
+
+        self.apply_outcomes();
+    }
+
+    #[cfg(feature = "parallel")]
+    pub fn update_parallel(&mut self) {
+        use rayon::prelude::*;
+
+        self.swap_buffers();
+        self.tick += 1;
+
+        let world = World {
+            front: &self.front,
+            seen: &self.seen,
+            perception: &self.perception,
+            tick: self.tick,
+            max_wait: self.max_wait,
+        };
+        self.outcomes.clear();
+        self.outcomes.par_extend(
+            self.roads
+                .par_iter_mut()
+                .flat_map(|road| {
+                    road.cars
+                        .par_iter_mut()
+                        .flat_map(|cars| cars.par_iter_mut())
+                })
+                .map(|car| step(car, &world)),
+        );
+
+        self.apply_outcomes();
+    }
+
+    /// ### swap_buffers
+    /// Remove the cars that are done and make the cars written last tick the new `front`.
+    /// Every car then broadcasts its state, and the messages received make the new `seen`.
+    fn swap_buffers(&mut self) {
+        // Cleanup and statistics logic
+        self.roads
+            .iter_mut()
+            .for_each(|road| road.cleanup_cars(&mut self.stats, self.tick));
+        self.admit_queued();
+        for road in &mut self.roads {
+            road.form_platoons(self.platooning, &mut self.stats);
         }
-        match direction {
-            Direction::North => {
-                let available_path = self.roads[0].get_available_path();
-                if let Some(path) = available_path {
-                    self.roads[0].add_car(Car::new(direction, path, self.total_cars));
-                    self.total_cars += 1;
-                }
+
+        self.front.rebuild(active_cars(&self.roads).map(Car::view));
+
+        let cars = self.roads.iter().flat_map(|r| r.cars.iter().flatten());
+        self.bus
+            .broadcast(self.tick, cars.map(Car::view), &mut self.stats);
+        self.bus.deliver(self.tick);
+
+        let (tick, stats) = (self.tick, &mut self.stats);
+        self.seen.rebuild(
+            self.bus
+                .received()
+                .into_iter()
+                .filter(|m| m.view.turning.lane() < 2 && (1..11).contains(&m.view.index))
+                .map(|m| {
+                    stats.set_message_age(tick - m.sent);
+                    m.view
+                }),
+        );
+    }
+
+    /// ### apply_outcomes
+    /// Record the statistics of every car in the same order as they were stepped, then let
+    /// the `Watchdog` break any deadlock.
+    fn apply_outcomes(&mut self) {
+        for outcome in &self.outcomes {
+            if outcome.collision {
+                self.stats.set_collisions()
+            } else if outcome.close_call {
+                self.stats.set_close_calls();
             }
-            Direction::East => {
-                let available_path = self.roads[1].get_available_path();
 
-                if let Some(path) = available_path {
-                    self.roads[1].add_car(Car::new(direction, path, self.total_cars));
-                    self.total_cars += 1;
-                }
+            if let Some(vel) = outcome.velocity {
+                self.stats.set_velocity(vel);
             }
-            Direction::South => {
-                let available_path = self.roads[2].get_available_path();
-                if let Some(path) = available_path {
-                    self.roads[2].add_car(Car::new(direction, path, self.total_cars));
-                    self.total_cars += 1;
-                }
+            self.stats.set_missed(outcome.missed);
+        }
+
+        for deadlock in self.watchdog.check(&mut self.roads, self.tick) {
+            self.stats.set_deadlock(deadlock);
+        }
+
+        if self
+            .tick
+            .is_multiple_of((SAMPLE_INTERVAL * FPS as f32) as u64)
+        {
+            self.stats
+                .set_sample(self.time(), self.queue_per_approach());
+        }
+
+        self.record_frame();
+    }
+
+    /// ### start_recording
+    /// Record every spawn and tick from now on, to be written by a `Recorder`.
+    pub fn start_recording(&mut self) {
+        self.events.get_or_insert_with(Vec::new);
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.events = None;
+    }
+
+    /// ### drain_events
+    /// Take the events recorded since the last call.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = Event> + '_ {
+        self.events.iter_mut().flat_map(|events| events.drain(..))
+    }
+
+    fn record_frame(&mut self) {
+        if self.events.is_none() {
+            return;
+        }
+        let ids = |f: fn(&Outcome) -> bool| -> Vec<usize> {
+            self.outcomes
+                .iter()
+                .filter(|o| f(o))
+                .map(|o| o.id)
+                .collect()
+        };
+        let frame = Frame {
+            tick: self.tick,
+            cars: self.cars().map(Into::into).collect(),
+            collisions: ids(|o| o.collision),
+            close_calls: ids(|o| o.close_call),
+        };
+        if let Some(events) = &mut self.events {
+            events.push(Event::Tick(frame));
+        }
+    }
+
+    /// ### add_car
+    /// Add a car from `direction` into a random available lane, or queue it in a random lane
+    /// if none is available. Lanes are picked by the turning ratios of the approach in
+    /// `demand`. Returns the id of the car.
+    pub fn add_car(&mut self, direction: Direction) -> Result<usize, SpawnError> {
+        let weights = self.demand.demand().approaches[direction.index()].turning;
+        match self.roads[direction.index()].get_available_path(weights) {
+            Some(turning) => self.add_car_turning(direction, turning),
+            None if self.entry_queue > 0 => {
+                let turning = Turning::choose(&Turning::ALL, weights)
+                    .unwrap_or_else(|| Turning::ALL[gen_range(0, Turning::ALL.len())]);
+                self.add_car_turning(direction, turning)
             }
-            Direction::West => {
-                let available_path = self.roads[3].get_available_path();
-                if let Some(path) = available_path {
-                    self.roads[3].add_car(Car::new(direction, path, self.total_cars));
-                    self.total_cars += 1;
-                }
+            None => {
+                self.stats.set_rejected();
+                Err(SpawnError::RoadBusy(direction))
+            }
+        }
+    }
+
+    /// ### add_car_turning
+    /// Add a car from `direction` into the lane for `turning`. If the lane is busy the car
+    /// waits in the entry queue of the lane, and is rejected when that queue is full.
+    /// Returns the id of the car.
+    pub fn add_car_turning(
+        &mut self,
+        direction: Direction,
+        turning: Turning,
+    ) -> Result<usize, SpawnError> {
+        if !self.is_lane_free(direction, turning) {
+            if self.roads[direction.index()].queued(&turning) >= self.entry_queue {
+                self.stats.set_rejected();
+                return Err(match self.entry_queue {
+                    0 => SpawnError::LaneBusy(direction, turning),
+                    _ => SpawnError::QueueFull(direction, turning),
+                });
             }
+            let car = Car::new(direction, turning, self.total_cars).spawned_at(self.tick);
+            self.roads[direction.index()].enqueue(car);
+            self.stats.set_delayed();
+        } else {
+            let car = Car::new(direction, turning, self.total_cars).spawned_at(self.tick);
+            self.enter(car);
         }
+        self.total_cars += 1;
+        Ok(self.total_cars - 1)
     }
 
-    pub fn get_all_cars(&self) -> Vec<Car> {
-        let mut cars = Vec::new();
-        for r in self.roads.iter() {
-            for car in r.cars.clone().iter().take(2).flatten() {
-                if (1..11).contains(&car.index) {
-                    cars.push(car.clone());
+    /// ### spawn_arrivals
+    /// Add the cars `demand` has arriving up to the current tick if `random` is on. The arrivals
+    /// are drawn either way, so turning it on does not spawn every car that arrived while it was
+    /// off. Arrivals in a busy lane are queued or rejected like any other car.
+    pub fn spawn_arrivals(&mut self) {
+        for (direction, turning) in self.demand.arrivals(self.tick) {
+            if self.random {
+                let _ = self.add_car_turning(direction, turning);
+            }
+        }
+    }
+
+    // Put the car on its road and record it for a replay
+    fn enter(&mut self, car: Car) {
+        if let Some(events) = &mut self.events {
+            events.push(Event::Spawn(Spawn {
+                tick: self.tick,
+                id: car.id,
+                direction: car.direction,
+                turning: car.turning,
+                model: car.model,
+            }));
+        }
+        self.roads[car.direction.index()].add_car(car);
+    }
+
+    /// ### admit_queued
+    /// Let the first car of every entry queue onto its lane once the lane is available.
+    fn admit_queued(&mut self) {
+        for direction in Direction::ALL {
+            for turning in Turning::ALL {
+                let road = &mut self.roads[direction.index()];
+                if !road.is_available(&turning) {
+                    continue;
+                }
+                if let Some(car) = road.dequeue(&turning) {
+                    self.enter(car);
                 }
             }
         }
+    }
+
+    /// ### is_lane_free
+    /// Check if a car added to the lane for `turning` from `direction` would enter right away.
+    pub fn is_lane_free(&self, direction: Direction, turning: Turning) -> bool {
+        let road = &self.roads[direction.index()];
+        road.is_available(&turning) && road.queued(&turning) == 0
+    }
+
+    /// ### is_queued
+    /// Check if the car is waiting in an entry queue.
+    pub fn is_queued(&self, id: usize) -> bool {
+        self.roads.iter().any(|road| road.is_queued(id))
+    }
+
+    /// ### queued_per_approach
+    /// Number of cars waiting in the entry queues of each `Road`, in the order of `roads`.
+    pub fn queued_per_approach(&self) -> [usize; 4] {
+        self.roads
+            .each_ref()
+            .map(|road| Turning::ALL.iter().map(|t| road.queued(t)).sum())
+    }
+
+    /// ### queue_per_approach
+    /// Number of cars standing still or waiting in an entry queue for each `Road`, in the order
+    /// of `roads`.
+    pub fn queue_per_approach(&self) -> [usize; 4] {
+        let mut queue = self.queued_per_approach();
+        for car in self.cars().filter(|car| car.vel == 0.0) {
+            queue[car.direction.index()] += 1;
+        }
+        queue
+    }
+
+    /// ### time
+    /// Simulated seconds since the start.
+    pub fn time(&self) -> f32 {
+        self.tick as f32 * DT
+    }
+
+    /// ### car_count
+    /// Number of cars currently in the world.
+    pub fn car_count(&self) -> usize {
+        self.cars_per_approach().iter().sum()
+    }
+
+    /// ### cars_per_approach
+    /// Number of cars currently in the world for each `Road`, in the order of `roads`.
+    pub fn cars_per_approach(&self) -> [usize; 4] {
+        self.roads
+            .each_ref()
+            .map(|road| road.cars.iter().map(Vec::len).sum())
+    }
+
+    /// ### platoon_count
+    /// Number of platoons currently in the world.
+    pub fn platoon_count(&self) -> usize {
+        self.cars()
+            .filter(|car| car.platoon == Some(car.id))
+            .count()
+    }
+
+    /// ### waiting_per_approach
+    /// Longest time a car currently in the world has stood still for each `Road`, in seconds.
+    pub fn waiting_per_approach(&self) -> [f32; 4] {
+        self.roads.each_ref().map(|road| {
+            road.cars
+                .iter()
+                .flatten()
+                .map(|car| car.waited as f32 * DT)
+                .fold(0.0, f32::max)
+        })
+    }
+
+    /// ### cars
+    /// Borrow every car in the world.
+    pub fn cars(&self) -> impl Iterator<Item = &Car> {
+        self.roads
+            .iter()
+            .flat_map(|road| road.cars.iter().flatten())
+    }
+
+    /// ### car
+    /// Find a car by its id.
+    pub fn car(&self, id: usize) -> Option<&Car> {
+        self.cars().find(|car| car.id == id)
+    }
+
+    /// ### car_at
+    /// Find the car covering the point `(x, y)`.
+    pub fn car_at(&self, x: f32, y: f32) -> Option<&Car> {
+        self.cars().find(|car| {
+            let borders = car.borders();
+            (borders.left..borders.right).contains(&x) && (borders.top..borders.bottom).contains(&y)
+        })
+    }
 
-        cars
+    /// ### active_cars
+    /// Borrow the cars in the left and straight lanes that are inside the intersection area.
+    pub fn active_cars(&self) -> impl Iterator<Item = &Car> {
+        active_cars(&self.roads)
     }
 
-    pub fn add_car_random(&mut self) {
+    pub fn add_car_random(&mut self) -> Result<usize, SpawnError> {
         match gen_range(0, 4) {
             0 => self.add_car(Direction::North),
             1 => self.add_car(Direction::East),
@@ -125,75 +548,73 @@ impl State {
     }
 }
 
-fn detect_close_call(car: &Car, other_cars: &[Car]) -> bool {
-    other_cars
-        .iter()
-        .any(|c| c.id != car.id && car.calc_dist(c) <= CLOSE_CALL_DISTANCE)
+/// The world as every car reads it during a tick.
+struct World<'a> {
+    front: &'a SpatialIndex,
+    seen: &'a SpatialIndex,
+    perception: &'a Perception,
+    tick: u64,
+    max_wait: u64,
 }
 
-fn detect_collision(car: &Car, other_cars: &[Car]) -> bool {
-    other_cars
-        .iter()
-        .any(|c| c.id != car.id && car.calc_dist(c) <= COLLISION_DISTANCE)
-}
+/// ### step
+/// Decide and move a single car on the cars it perceives of the cars it has `seen`. Only reads
+/// from the `World`, so cars can be stepped in any order.
+fn step(car: &mut Car, world: &World) -> Outcome {
+    let collision = detect_collision(car, world.front);
+    let close_call = !collision && detect_close_call(car, world.front);
 
-fn detect_deadlock(other_cars: &[Car], car: &mut Car) -> bool {
-    if car.turning != Turning::Left {
-        return false;
-    }
+    let seen = world.perception.perceive(car, world.seen, world.tick);
+    let missed = seen.missed();
 
-    let middle_sectors = [(5, 5), (5, 6), (6, 5), (6, 6)];
-    let cars: Vec<&Car> = other_cars
-        .iter()
-        .filter(|&c| middle_sectors.contains(&(c.sector(0).get_x(), c.sector(0).get_y())))
-        .collect();
+    let velocity = if let Some(id) = car.holding_for {
+        car.stop();
+        car.braked(BrakeReason::RightOfWay, Some(id));
+        None
+    } else {
+        // The cars holding for it are out of its way, unless they stand on its path
+        let seen = if car.right_of_way {
+            seen.with_right_of_way()
+        } else {
+            seen
+        };
+        let velocity = Some(car.vel);
+        car.move_car(&seen);
+        car.give_way(&seen);
+        velocity
+    };
 
-    if car.index == 3 && car.sector_pos() > SECTOR_WIDTH - MARGIN {
-        return cars.len() >= 2;
+    if car.vel == 0.0 {
+        car.waited += 1;
     }
+    car.priority = car.waited >= world.max_wait;
 
-    if car.index == 4 && car.sector_pos() > SECTOR_WIDTH - MARGIN {
-        let north = cars
-            .iter()
-            .filter(|c| c.direction == Direction::North)
-            .count();
-        let east = cars
-            .iter()
-            .filter(|c| c.direction == Direction::East)
-            .count();
-        let south = cars
-            .iter()
-            .filter(|c| c.direction == Direction::South)
-            .count();
-        let west = cars
-            .iter()
-            .filter(|c| c.direction == Direction::West)
-            .count();
-        match car.direction {
-            Direction::West => {
-                if north >= 2 || east >= 2 || south >= 2 {
-                    return false;
-                }
-            }
-            Direction::South => {
-                if north >= 2 || east >= 2 || west >= 2 {
-                    return false;
-                }
-            }
-            Direction::North => {
-                if west >= 2 || east >= 2 || south >= 2 {
-                    return false;
-                }
-            }
-            Direction::East => {
-                if north >= 2 || west >= 2 || south >= 2 {
-                    return false;
-                }
-            }
-        }
-        return cars.len() >= 3;
+    Outcome {
+        id: car.id,
+        collision,
+        close_call,
+        velocity,
+        missed,
     }
-    false
+}
+
+fn active_cars(roads: &[Road]) -> impl Iterator<Item = &Car> {
+    roads
+        .iter()
+        .flat_map(|r| r.cars.iter().take(2).flatten())
+        .filter(|car| (1..11).contains(&car.index))
+}
+
+fn detect_close_call(car: &Car, other_cars: &SpatialIndex) -> bool {
+    other_cars
+        .nearby(car.center_car(), CLOSE_CALL_DISTANCE)
+        .any(|c| c.id != car.id && car.calc_dist(c) <= CLOSE_CALL_DISTANCE)
+}
+
+fn detect_collision(car: &Car, other_cars: &SpatialIndex) -> bool {
+    other_cars
+        .nearby(car.center_car(), COLLISION_DISTANCE)
+        .any(|c| c.id != car.id && car.calc_dist(c) <= COLLISION_DISTANCE)
 }
 
 impl Default for State {
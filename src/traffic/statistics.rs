@@ -1,6 +1,30 @@
+use std::collections::VecDeque;
+
 use crate::config::FPS;
+use crate::traffic::watchdog::Deadlock;
+use crate::traffic::Direction;
+
+/// Simulated seconds over which the throughput is measured.
+pub const THROUGHPUT_WINDOW: f32 = 60.0;
+
+/// Simulated seconds between two `Sample`s.
+pub const SAMPLE_INTERVAL: f32 = 60.0;
+
+/// ### Sample
+/// Queue and delay per approach at `time`, in the order of `Direction::ALL`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Sample {
+    pub time: f32,
+    /// Cars standing still or waiting in an entry queue.
+    pub queue: [usize; 4],
+    /// Number of cars that left since the last sample.
+    pub exits: [usize; 4],
+    /// Mean time the cars that left since the last sample took longer than they would have
+    /// alone in the world, in seconds, see `Car::delay`.
+    pub delay: [f32; 4],
+}
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct Statistics {
     max_vehicles: usize,
     max_velocity: f32,
@@ -9,6 +33,35 @@ pub struct Statistics {
     min_time: f32,
     close_calls: u32,
     collisions: u32,
+    finished: usize,
+    total_time: f32,
+    exits: VecDeque<f32>,
+    deadlocks: Vec<Deadlock>,
+    /// Longest and total wait of the cars that left, per approach.
+    max_wait: [f32; 4],
+    total_wait: [f32; 4],
+    waited: [usize; 4],
+    rejected: usize,
+    delayed: usize,
+    samples: Vec<Sample>,
+    /// Platoons and the cars in them summed over every tick, and the largest platoon.
+    platoons: usize,
+    platooned: usize,
+    max_platoon: usize,
+    /// Time gaps of moving cars in a platoon to the car in front of them, summed over every tick.
+    platoon_gap: f32,
+    platoon_gaps: usize,
+    /// Messages sent and lost over the `MessageBus`, and the age in ticks of the messages
+    /// the cars decided on, summed over every tick.
+    messages: usize,
+    lost_messages: usize,
+    message_age: u64,
+    message_ages: usize,
+    /// Cars within `SCAN_DISTANCE` of a car that it did not perceive, summed over every tick.
+    missed: usize,
+    /// Exits and total delay per approach since the last sample.
+    interval_exits: [usize; 4],
+    interval_delay: [f32; 4],
 }
 
 impl Statistics {
@@ -21,6 +74,28 @@ impl Statistics {
             min_time: 0.0,
             close_calls: 0,
             collisions: 0,
+            finished: 0,
+            total_time: 0.0,
+            exits: VecDeque::new(),
+            deadlocks: Vec::new(),
+            max_wait: [0.0; 4],
+            total_wait: [0.0; 4],
+            waited: [0; 4],
+            rejected: 0,
+            delayed: 0,
+            samples: Vec::new(),
+            platoons: 0,
+            platooned: 0,
+            max_platoon: 0,
+            platoon_gap: 0.0,
+            platoon_gaps: 0,
+            messages: 0,
+            lost_messages: 0,
+            message_age: 0,
+            message_ages: 0,
+            missed: 0,
+            interval_exits: [0; 4],
+            interval_delay: [0.0; 4],
         }
     }
 
@@ -67,6 +142,24 @@ impl Statistics {
             self.min_time = min_time;
         }
     }
+
+    /// ### set_exit
+    /// Record a car leaving the intersection at simulated time `now` after `time` seconds.
+    pub fn set_exit(&mut self, now: f32, time: f32) {
+        self.set_time(time);
+        self.finished += 1;
+        self.total_time += time;
+
+        self.exits.push_back(now);
+        while self
+            .exits
+            .front()
+            .is_some_and(|&exit| exit < now - THROUGHPUT_WINDOW)
+        {
+            self.exits.pop_front();
+        }
+    }
+
     pub fn set_close_calls(&mut self) {
         self.close_calls += 1;
     }
@@ -75,6 +168,93 @@ impl Statistics {
         self.collisions += 1;
     }
 
+    /// ### set_wait
+    /// Record a car from `direction` leaving after standing still for `wait` seconds.
+    pub fn set_wait(&mut self, direction: &Direction, wait: f32) {
+        let i = direction.index();
+        self.max_wait[i] = self.max_wait[i].max(wait);
+        self.total_wait[i] += wait;
+        self.waited[i] += 1;
+    }
+
+    /// ### set_delay
+    /// Record a car from `direction` leaving `delay` seconds later than it would have alone in
+    /// the world, for the next `Sample`.
+    pub fn set_delay(&mut self, direction: &Direction, delay: f32) {
+        let i = direction.index();
+        self.interval_exits[i] += 1;
+        self.interval_delay[i] += delay;
+    }
+
+    /// ### set_sample
+    /// Record the `queue` per approach at `time`, with the delay of the cars that left since
+    /// the last sample.
+    pub fn set_sample(&mut self, time: f32, queue: [usize; 4]) {
+        let exits = std::mem::take(&mut self.interval_exits);
+        let total = std::mem::take(&mut self.interval_delay);
+        let delay = std::array::from_fn(|i| match exits[i] {
+            0 => 0.0,
+            n => total[i] / n as f32,
+        });
+        self.samples.push(Sample {
+            time,
+            queue,
+            exits,
+            delay,
+        });
+    }
+
+    /// ### set_platoon
+    /// Record a platoon of `size` cars during a tick.
+    pub fn set_platoon(&mut self, size: usize) {
+        self.platoons += 1;
+        self.platooned += size;
+        self.max_platoon = self.max_platoon.max(size);
+    }
+
+    /// ### set_platoon_gap
+    /// Record the time gap in seconds between a car in a platoon and the car in front of it.
+    pub fn set_platoon_gap(&mut self, gap: f32) {
+        self.platoon_gap += gap;
+        self.platoon_gaps += 1;
+    }
+
+    /// ### set_message
+    /// Record a message sent over the `MessageBus`, and whether it got lost.
+    pub fn set_message(&mut self, lost: bool) {
+        self.messages += 1;
+        self.lost_messages += lost as usize;
+    }
+
+    /// ### set_message_age
+    /// Record the age in ticks of a message a car decided on.
+    pub fn set_message_age(&mut self, age: u64) {
+        self.message_age += age;
+        self.message_ages += 1;
+    }
+
+    /// ### set_missed
+    /// Record the number of cars close to a car that it did not perceive during a tick.
+    pub fn set_missed(&mut self, missed: usize) {
+        self.missed += missed;
+    }
+
+    /// ### set_rejected
+    /// Record a car that could not be added because its lane was busy.
+    pub fn set_rejected(&mut self) {
+        self.rejected += 1;
+    }
+
+    /// ### set_delayed
+    /// Record a car that had to wait in an entry queue because its lane was busy.
+    pub fn set_delayed(&mut self) {
+        self.delayed += 1;
+    }
+
+    pub fn set_deadlock(&mut self, deadlock: Deadlock) {
+        self.deadlocks.push(deadlock);
+    }
+
     // Getters
     pub fn max_vehicles(&self) -> usize {
         self.max_vehicles
@@ -91,6 +271,28 @@ impl Statistics {
     pub fn min_time(&self) -> f32 {
         self.min_time
     }
+    pub fn finished(&self) -> usize {
+        self.finished
+    }
+
+    /// ### mean_time
+    /// Running mean of the time spent by cars that have left the intersection.
+    pub fn mean_time(&self) -> f32 {
+        if self.finished == 0 {
+            return 0.0;
+        }
+        self.total_time / self.finished as f32
+    }
+
+    /// ### throughput
+    /// Number of cars that left the intersection in the last `THROUGHPUT_WINDOW` seconds.
+    pub fn throughput(&self, now: f32) -> usize {
+        self.exits
+            .iter()
+            .filter(|&&exit| exit >= now - THROUGHPUT_WINDOW)
+            .count()
+    }
+
     pub fn close_calls(&self) -> u32 {
         (self.close_calls / 2) / FPS as u32
     }
@@ -98,6 +300,106 @@ impl Statistics {
     pub fn collisions(&self) -> u32 {
         (self.collisions / 2) / FPS as u32
     }
+
+    /// ### close_call_events
+    /// Number of times a car was within `CLOSE_CALL_DISTANCE` of another car during a tick.
+    /// Unlike `close_calls`, even a single tick counts.
+    pub fn close_call_events(&self) -> u32 {
+        self.close_calls
+    }
+
+    /// ### collision_events
+    /// Number of times a car was within `COLLISION_DISTANCE` of another car during a tick.
+    /// Unlike `collisions`, even a single tick counts.
+    pub fn collision_events(&self) -> u32 {
+        self.collisions
+    }
+
+    /// ### max_wait
+    /// Longest time any car that left stood still, in seconds.
+    pub fn max_wait(&self) -> f32 {
+        self.max_wait.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// ### approach_wait
+    /// Mean and longest time the cars that left from `direction` stood still, in seconds.
+    pub fn approach_wait(&self, direction: &Direction) -> (f32, f32) {
+        let i = direction.index();
+        if self.waited[i] == 0 {
+            return (0.0, 0.0);
+        }
+        (self.total_wait[i] / self.waited[i] as f32, self.max_wait[i])
+    }
+
+    pub fn rejected(&self) -> usize {
+        self.rejected
+    }
+    pub fn delayed(&self) -> usize {
+        self.delayed
+    }
+
+    /// ### mean_platoon_size
+    /// Mean number of cars in a platoon, over every tick a platoon existed.
+    pub fn mean_platoon_size(&self) -> f32 {
+        if self.platoons == 0 {
+            return 0.0;
+        }
+        self.platooned as f32 / self.platoons as f32
+    }
+
+    pub fn max_platoon_size(&self) -> usize {
+        self.max_platoon
+    }
+
+    /// ### mean_platoon_gap
+    /// Mean time gap in seconds between a moving car in a platoon and the car in front of it.
+    pub fn mean_platoon_gap(&self) -> f32 {
+        if self.platoon_gaps == 0 {
+            return 0.0;
+        }
+        self.platoon_gap / self.platoon_gaps as f32
+    }
+
+    pub fn messages(&self) -> usize {
+        self.messages
+    }
+    pub fn lost_messages(&self) -> usize {
+        self.lost_messages
+    }
+
+    pub fn missed(&self) -> usize {
+        self.missed
+    }
+
+    /// ### message_loss
+    /// Fraction of the messages sent that got lost, from 0 to 1.
+    pub fn message_loss(&self) -> f32 {
+        if self.messages == 0 {
+            return 0.0;
+        }
+        self.lost_messages as f32 / self.messages as f32
+    }
+
+    /// ### mean_message_age
+    /// Mean age in seconds of the messages the cars decided on.
+    pub fn mean_message_age(&self) -> f32 {
+        if self.message_ages == 0 {
+            return 0.0;
+        }
+        self.message_age as f32 / self.message_ages as f32 / FPS as f32
+    }
+
+    /// ### samples
+    /// Queue and delay per approach every `SAMPLE_INTERVAL` seconds.
+    pub fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    /// ### deadlocks
+    /// Every deadlock broken by the `Watchdog`, in the order they happened.
+    pub fn deadlocks(&self) -> &[Deadlock] {
+        &self.deadlocks
+    }
 }
 
 impl Default for Statistics {
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::config::FPS;
+use crate::traffic::car::CarView;
+use crate::traffic::statistics::Statistics;
+
+/// Default number of ticks after which a car that has not been heard from is forgotten.
+pub const MESSAGE_TIMEOUT: u64 = FPS / 2;
+
+/// ### Channel
+/// How messages travel between cars. The default channel delivers every message in the same
+/// tick it was sent. Channels are read from JSON, for example a channel that delays messages
+/// by 100 to 200 ms and loses one in ten:
+///
+/// ```json
+/// { "latency": 6, "jitter": 6, "loss": 0.1, "seed": 7 }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Channel {
+    /// Ticks every message takes to arrive.
+    pub latency: u64,
+    /// Up to this many ticks added to the latency of every message, uniformly at random.
+    pub jitter: u64,
+    /// Probability of a message getting lost, from 0 to 1.
+    pub loss: f32,
+    /// Ticks after the last message from a car arrived after which the car is forgotten.
+    pub timeout: u64,
+    pub seed: u64,
+}
+
+impl Default for Channel {
+    fn default() -> Channel {
+        Channel {
+            latency: 0,
+            jitter: 0,
+            loss: 0.0,
+            timeout: MESSAGE_TIMEOUT,
+            seed: 0,
+        }
+    }
+}
+
+impl Channel {
+    pub fn load(path: impl AsRef<Path>) -> Result<Channel, ChannelError> {
+        let json = fs::read_to_string(path).map_err(ChannelError::Io)?;
+        Channel::parse(&json)
+    }
+
+    pub fn parse(json: &str) -> Result<Channel, ChannelError> {
+        serde_json::from_str(json).map_err(ChannelError::Parse)
+    }
+}
+
+#[derive(Debug)]
+pub enum ChannelError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for ChannelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelError::Io(err) => write!(f, "could not read channel: {err}"),
+            ChannelError::Parse(err) => write!(f, "invalid channel: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ChannelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ChannelError::Io(err) => Some(err),
+            ChannelError::Parse(err) => Some(err),
+        }
+    }
+}
+
+/// ### Message
+/// The state a car broadcast at tick `sent`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Message {
+    pub sent: u64,
+    pub view: CarView,
+}
+
+/// ### MessageBus
+/// Carries the state every car broadcasts each tick to the other cars, over a `Channel` that
+/// can delay and lose messages. Cars decide on the latest message received from every other
+/// car instead of on its actual state.
+///
+/// The intersection relays every message, so a message arrives at every car at the same time
+/// or is lost for all of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageBus {
+    channel: Channel,
+    rng: StdRng,
+    /// Messages on their way and the tick they arrive at.
+    in_flight: Vec<(u64, Message)>,
+    /// Latest message received from every car.
+    received: HashMap<usize, Message>,
+    /// Tick the last message from every car arrived at.
+    heard: HashMap<usize, u64>,
+}
+
+impl Default for MessageBus {
+    fn default() -> MessageBus {
+        MessageBus::new(Channel::default())
+    }
+}
+
+impl MessageBus {
+    pub fn new(channel: Channel) -> MessageBus {
+        MessageBus {
+            channel,
+            rng: StdRng::seed_from_u64(channel.seed),
+            in_flight: Vec::new(),
+            received: HashMap::new(),
+            heard: HashMap::new(),
+        }
+    }
+
+    pub fn channel(&self) -> &Channel {
+        &self.channel
+    }
+
+    /// ### broadcast
+    /// Send the state of every car at `tick`.
+    pub fn broadcast(
+        &mut self,
+        tick: u64,
+        views: impl IntoIterator<Item = CarView>,
+        stats: &mut Statistics,
+    ) {
+        for view in views {
+            let lost = self.channel.loss > 0.0 && self.rng.gen::<f32>() < self.channel.loss;
+            stats.set_message(lost);
+            if lost {
+                continue;
+            }
+            let jitter = match self.channel.jitter {
+                0 => 0,
+                jitter => self.rng.gen_range(0..=jitter),
+            };
+            let arrives = tick + self.channel.latency + jitter;
+            self.in_flight.push((arrives, Message { sent: tick, view }));
+        }
+    }
+
+    /// ### deliver
+    /// Receive the messages arriving up to `tick` and forget the cars that have not been heard
+    /// from for `Channel::timeout` ticks. A message older than the one already received from
+    /// the same car is dropped. The timeout counts from the tick a message arrived, so a channel
+    /// slower than the timeout still delivers.
+    pub fn deliver(&mut self, tick: u64) {
+        let mut i = 0;
+        while i < self.in_flight.len() {
+            if self.in_flight[i].0 > tick {
+                i += 1;
+                continue;
+            }
+            let (arrived, message) = self.in_flight.swap_remove(i);
+            let heard = self.heard.entry(message.view.id).or_insert(arrived);
+            *heard = arrived.max(*heard);
+            let latest = self.received.entry(message.view.id).or_insert(message);
+            if latest.sent < message.sent {
+                *latest = message;
+            }
+        }
+
+        let timeout = self.channel.timeout;
+        self.heard.retain(|_, heard| tick - *heard <= timeout);
+        let heard = &self.heard;
+        self.received.retain(|id, _| heard.contains_key(id));
+    }
+
+    /// ### received
+    /// Latest message received from every car that has not timed out, in the order the cars
+    /// are in on the roads.
+    pub fn received(&self) -> Vec<Message> {
+        let mut received: Vec<Message> = self.received.values().copied().collect();
+        // The same order as the cars on the roads, so a perfect channel decides like the cars
+        // would on their actual state
+        received.sort_by_key(|m| (m.view.direction.index(), m.view.turning.lane(), m.view.id));
+        received
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+use crate::config::FPS;
+use crate::traffic::path::{Path, APPROACH_LENGTH};
+use crate::traffic::road::Road;
+use crate::traffic::Car;
+
+/// Ticks every car in a wait-for cycle has to stand still before it counts as a deadlock.
+pub const DEADLOCK_TICKS: u64 = FPS;
+
+/// Number of sectors of every loop kept free of cars on their way around it. One free sector
+/// is enough to keep a loop moving, a second one leaves the cars in it room to keep their
+/// distance.
+pub const LOOP_SLACK: usize = 2;
+
+/// ### Deadlock
+/// A cycle of cars waiting for each other, and the car given right of way to break it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deadlock {
+    pub tick: u64,
+    /// Ids of the cars in the cycle, every car waiting for the next and the last for the first.
+    pub cycle: Vec<usize>,
+    /// Id of the car the other cars in the cycle hold for until it has left the intersection.
+    pub granted: usize,
+}
+
+// Where a car was last tick and since which tick it has been standing there
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Stall {
+    x: f32,
+    y: f32,
+    since: u64,
+}
+
+/// ### Watchdog
+/// Keeps the cars in the intersection from locking each other in, by telling cars to hold for
+/// another car.
+///
+/// Cars hold in the first sector of the intersection while one of the `Path::loops` on the
+/// rest of their way has all but `LOOP_SLACK` of its sectors taken by cars on their way around
+/// it, so no loop ever fills up with cars waiting for each other. Cars are let in in the order
+/// they reached the first sector.
+///
+/// Any other deadlock is found in the wait-for graph between cars, where every car points to
+/// the car it is braking for. A cycle is a deadlock once none of its cars have moved for
+/// `DEADLOCK_TICKS`. One car of the cycle is given right of way: the other cars of the cycle
+/// hold until it has left the intersection, and it keeps driving as if they were not there
+/// unless they are on its `Path`. The car given right of way is the one that has been standing
+/// still the longest, or the one with the lowest id when that is a tie, preferring cars with
+/// a free next sector and none of the other cars of the cycle on their way. A car that has not
+/// moved for `DEADLOCK_TICKS` after it got right of way can lose it to a car of a new cycle.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Watchdog {
+    stalls: HashMap<usize, Stall>,
+    /// Cars with right of way, and the cars holding for each of them.
+    grants: Vec<(usize, Vec<usize>)>,
+    /// Tick every car in the first sector of the intersection got there.
+    arrivals: HashMap<usize, u64>,
+}
+
+impl Watchdog {
+    pub fn new() -> Watchdog {
+        Watchdog::default()
+    }
+
+    /// ### check
+    /// Track which cars stand still at `tick`, give right of way to break every deadlock
+    /// among them and tell every car which car to hold for.
+    pub fn check(&mut self, roads: &mut [Road], tick: u64) -> Vec<Deadlock> {
+        self.track(roads, tick);
+        let by_id: HashMap<usize, &Car> = cars(roads).map(|car| (car.id, car)).collect();
+        self.grants
+            .retain(|(id, _)| by_id.get(id).is_some_and(|car| in_intersection(car)));
+
+        // The order of the cars in `roads`, so cycles are found the same way every run
+        let order: Vec<usize> = cars(roads).map(|car| car.id).collect();
+        let waits: HashMap<usize, usize> = cars(roads)
+            .filter(|car| self.stalled_for(car.id, tick) >= DEADLOCK_TICKS)
+            .filter_map(|car| Some((car.id, car.braking_for?)))
+            .collect();
+
+        let cycles = find_cycles(&order, &waits);
+        // The cars in every sector, only needed to break a deadlock
+        let mut taken: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        if !cycles.is_empty() {
+            for car in by_id.values() {
+                let sector = car.sector(0);
+                let at = (sector.get_x(), sector.get_y());
+                taken.entry(at).or_default().push(car.id);
+            }
+        }
+
+        let mut deadlocks = Vec::new();
+        for cycle in cycles {
+            // A car given right of way that is still stuck gives it up to a car of the new cycle
+            self.grants.retain(|(granted, _)| !cycle.contains(granted));
+            let granted = *cycle
+                .iter()
+                .min_by_key(|&&id| {
+                    let clear = is_clear(&by_id, &taken, id, &cycle);
+                    (!clear, self.stalls[&id].since, id)
+                })
+                .expect("a cycle has at least one car");
+            // The car gets `DEADLOCK_TICKS` to move before the right of way goes to another car
+            if let Some(stall) = self.stalls.get_mut(&granted) {
+                stall.since = tick;
+            }
+            let holding = cycle.iter().copied().filter(|&id| id != granted).collect();
+            self.grants.push((granted, holding));
+            deadlocks.push(Deadlock {
+                tick,
+                cycle,
+                granted,
+            });
+        }
+
+        let mut holds: HashMap<usize, usize> = self
+            .grants
+            .iter()
+            .flat_map(|(granted, holding)| holding.iter().map(move |&id| (id, *granted)))
+            .collect();
+        holds.extend(admit(roads, &self.arrivals));
+        for car in roads
+            .iter_mut()
+            .flat_map(|road| road.cars.iter_mut().flatten())
+        {
+            car.right_of_way = self.is_granted(car.id);
+            car.holding_for = holds.get(&car.id).copied();
+        }
+        deadlocks
+    }
+
+    fn is_granted(&self, id: usize) -> bool {
+        self.grants.iter().any(|&(granted, _)| granted == id)
+    }
+
+    // Number of ticks the car has not moved for
+    fn stalled_for(&self, id: usize, tick: u64) -> u64 {
+        self.stalls.get(&id).map_or(0, |stall| tick - stall.since)
+    }
+
+    fn track(&mut self, roads: &[Road], tick: u64) {
+        let mut stalls = HashMap::with_capacity(self.stalls.len());
+        for car in cars(roads) {
+            let since = match self.stalls.get(&car.id) {
+                Some(stall) if stall.x == car.x && stall.y == car.y => stall.since,
+                _ => tick,
+            };
+            let (x, y) = (car.x, car.y);
+            stalls.insert(car.id, Stall { x, y, since });
+        }
+        self.stalls = stalls;
+
+        let arrivals = cars(roads)
+            .filter(|car| car.index == APPROACH_LENGTH)
+            .map(|car| (car.id, self.arrivals.get(&car.id).copied().unwrap_or(tick)));
+        self.arrivals = arrivals.collect();
+    }
+}
+
+fn cars(roads: &[Road]) -> impl Iterator<Item = &Car> {
+    roads.iter().flat_map(|road| road.cars.iter().flatten())
+}
+
+// Whether the car has entered the intersection and not yet reached the road it leaves on
+fn in_intersection(car: &Car) -> bool {
+    (APPROACH_LENGTH..car.path.sectors.len() - APPROACH_LENGTH).contains(&car.index)
+}
+
+/// ### is_clear
+/// Whether the next sector in the `Path` of the car is free and no other car of the `cycle`
+/// is on the rest of its way through the intersection.
+fn is_clear(
+    by_id: &HashMap<usize, &Car>,
+    taken: &HashMap<(usize, usize), Vec<usize>>,
+    id: usize,
+    cycle: &[usize],
+) -> bool {
+    let Some(car) = by_id.get(&id) else {
+        return false;
+    };
+    let ahead = &car.path.sectors[car.index + 1..];
+    let next_free = ahead.first().is_none_or(|next| {
+        taken
+            .get(&(next.get_x(), next.get_y()))
+            .is_none_or(|ids| ids.iter().all(|&other| other == id))
+    });
+    next_free
+        && cycle
+            .iter()
+            .filter(|&&other| other != id)
+            .filter_map(|other| by_id.get(other))
+            .all(|other| !ahead.contains(&other.sector(0)))
+}
+
+/// ### admit
+/// Get the cars that have to hold in the first sector of the intersection, and the car each
+/// of them holds for, the last car before it on its way around the full loop.
+fn admit(roads: &[Road], arrivals: &HashMap<usize, u64>) -> Vec<(usize, usize)> {
+    let loops = Path::loops();
+    let mut riders: Vec<Vec<usize>> = vec![Vec::new(); loops.len()];
+    for car in cars(roads).filter(|car| car.index > APPROACH_LENGTH) {
+        for &i in car.path.loops_from(car.index) {
+            riders[i].push(car.id);
+        }
+    }
+
+    // First come, first served, and a car that holds keeps its place in the loops ahead of it
+    // from the cars after it
+    let mut waiting: Vec<&Car> = cars(roads)
+        .filter(|car| car.index == APPROACH_LENGTH)
+        .collect();
+    waiting.sort_by_key(|car| (arrivals[&car.id], car.id));
+    let mut holds = Vec::new();
+    for car in waiting {
+        let ahead = car.path.loops_from(car.index);
+        if let Some(&i) = ahead
+            .iter()
+            .find(|&&i| riders[i].len() + LOOP_SLACK >= loops[i].len())
+        {
+            holds.push((car.id, *riders[i].last().expect("a full loop has cars")));
+        }
+        ahead.iter().for_each(|&i| riders[i].push(car.id));
+    }
+    holds
+}
+
+/// ### find_cycles
+/// Every car waits for at most one other car, so following the waits from each car either
+/// ends at a car that is not waiting, or runs into a cycle.
+fn find_cycles(order: &[usize], waits: &HashMap<usize, usize>) -> Vec<Vec<usize>> {
+    let mut visited: HashMap<usize, usize> = HashMap::new();
+    let mut cycles = Vec::new();
+    for (walk, &start) in order.iter().enumerate() {
+        let mut path = Vec::new();
+        let mut id = start;
+        while !visited.contains_key(&id) {
+            visited.insert(id, walk);
+            path.push(id);
+            match waits.get(&id) {
+                Some(&next) => id = next,
+                None => break,
+            }
+        }
+        // Running into a car of this walk closes a cycle, a car of an earlier walk does not
+        if visited.get(&id) == Some(&walk) && waits.contains_key(&id) {
+            if let Some(i) = path.iter().position(|&c| c == id) {
+                cycles.push(path.split_off(i));
+            }
+        }
+    }
+    cycles
+}
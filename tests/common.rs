@@ -1,23 +1,25 @@
 #![allow(dead_code)]
+#![allow(clippy::needless_return)]
 
-use smart_road::traffic::State;
+use smart_road::traffic::scenario::Scenario;
+use smart_road::traffic::{Direction, State, Turning};
 pub async fn setup() -> State {
     let mut state = State::new();
     for _ in 0..=50 {
         for _ in 0..2 {
-            state.add_car_random();
-            state.add_car_random();
-            state.add_car_random();
-            state.add_car_random();
+            let _ = state.add_car_random();
+            let _ = state.add_car_random();
+            let _ = state.add_car_random();
+            let _ = state.add_car_random();
             for _ in 0..200 {
                 state.update();
             }
         }
 
-        state.add_car_random();
-        state.add_car_random();
-        state.add_car_random();
-        state.add_car_random();
+        let _ = state.add_car_random();
+        let _ = state.add_car_random();
+        let _ = state.add_car_random();
+        let _ = state.add_car_random();
 
         // Simulate traffic for a certain number of iterations
         let simulation_iterations = 10000;
@@ -27,3 +29,62 @@ pub async fn setup() -> State {
     }
     return state;
 }
+
+/// Load a scenario from `scenarios/`.
+pub fn load(name: &str) -> Scenario {
+    Scenario::load(format!("{}/scenarios/{name}", env!("CARGO_MANIFEST_DIR"))).unwrap()
+}
+
+/// Spawn `cars` cars going straight from the north, each one as soon as the lane is free.
+pub fn spawn_line(state: &mut State, cars: usize) -> Vec<usize> {
+    let mut ids = Vec::new();
+    while ids.len() < cars {
+        if state.is_lane_free(Direction::North, Turning::Straight) {
+            ids.push(
+                state
+                    .add_car_turning(Direction::North, Turning::Straight)
+                    .unwrap(),
+            );
+        }
+        state.update();
+    }
+    ids
+}
+
+/// Run `scenario` twice, without its expectations, and check both runs end the same.
+pub fn assert_deterministic(mut scenario: Scenario) -> State {
+    scenario.expect.clear();
+    let first = scenario.run().unwrap();
+    let second = scenario.run().unwrap();
+    assert_eq!(first.stats, second.stats);
+    first
+}
+
+/// Fill `state` with random cars, then update a copy of it in parallel for `ticks` and check
+/// every car stays bit-identical to the sequential update every tick.
+#[cfg(feature = "parallel")]
+pub fn assert_parallel(mut sequential: State, ticks: usize) {
+    for _ in 0..20 {
+        let _ = sequential.add_car_random();
+        let _ = sequential.add_car_random();
+        for _ in 0..50 {
+            sequential.update_sequential();
+        }
+    }
+
+    // `Car` compares by id only, so compare what every car looks like
+    let cars = |state: &State| -> Vec<_> {
+        state
+            .roads
+            .iter()
+            .flat_map(|r| r.cars.iter().flatten().map(|c| c.view()))
+            .collect()
+    };
+    let mut parallel = sequential.clone();
+    for _ in 0..ticks {
+        sequential.update_sequential();
+        parallel.update_parallel();
+        assert_eq!(sequential, parallel);
+        assert_eq!(cars(&sequential), cars(&parallel));
+    }
+}
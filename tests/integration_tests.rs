@@ -3,7 +3,9 @@ mod common;
 mod test_config {}
 mod test_state {
     use crate::common;
+    use smart_road::config::SECTOR_WIDTH;
     use smart_road::traffic::state::*;
+    use smart_road::traffic::{path, BrakeReason, Car, Path, Turning};
 
     #[test]
     fn test_constructor() {
@@ -23,10 +25,10 @@ mod test_state {
         let mut state = State::default();
         // Add cars
         for _ in 0..10 {
-            state.add_car(Direction::North);
-            state.add_car(Direction::East);
-            state.add_car(Direction::South);
-            state.add_car(Direction::West);
+            let _ = state.add_car(Direction::North);
+            let _ = state.add_car(Direction::East);
+            let _ = state.add_car(Direction::South);
+            let _ = state.add_car(Direction::West);
         }
 
         // Check if len of path is longer than 0
@@ -43,6 +45,138 @@ mod test_state {
         }
     }
 
+    #[test]
+    fn test_add_car_turning() {
+        let mut state = State::default();
+        assert_eq!(
+            state.add_car_turning(Direction::East, Turning::Right),
+            Ok(0)
+        );
+        assert_eq!(
+            state.add_car_turning(Direction::East, Turning::Right),
+            Err(SpawnError::LaneBusy(Direction::East, Turning::Right))
+        );
+        assert_eq!(state.add_car_turning(Direction::West, Turning::Left), Ok(1));
+
+        assert_eq!(state.roads[1].cars[2].len(), 1);
+        assert_eq!(state.roads[3].cars[0].len(), 1);
+        assert_eq!(state.car_count(), 2);
+        assert_eq!(state.roads[3].cars[0][0].turning, Turning::Left);
+    }
+
+    #[test]
+    fn test_choose_turning() {
+        for _ in 0..100 {
+            assert_eq!(
+                Turning::choose(&Turning::ALL, [0.0, 1.0, 0.0]),
+                Some(Turning::Straight)
+            );
+            assert_ne!(
+                Turning::choose(&Turning::ALL, [1.0, 0.0, 1.0]),
+                Some(Turning::Straight)
+            );
+            assert_eq!(
+                Turning::choose(&[Turning::Left, Turning::Right], [1.0, 1.0, 0.0]),
+                Some(Turning::Left)
+            );
+        }
+        assert_eq!(Turning::choose(&Turning::ALL, [0.0; 3]), None);
+        assert_eq!(Turning::choose(&[], [1.0; 3]), None);
+    }
+
+    #[test]
+    fn test_entry_queue() {
+        let mut state = State::default();
+        for turning in Turning::ALL {
+            state.add_car_turning(Direction::North, turning).unwrap();
+        }
+        assert_eq!(
+            state.add_car(Direction::North),
+            Err(SpawnError::RoadBusy(Direction::North))
+        );
+
+        state.entry_queue = 2;
+        assert_eq!(
+            state.add_car_turning(Direction::East, Turning::Right),
+            Ok(3)
+        );
+        assert_eq!(
+            state.add_car_turning(Direction::East, Turning::Right),
+            Ok(4)
+        );
+        assert_eq!(
+            state.add_car_turning(Direction::East, Turning::Right),
+            Ok(5)
+        );
+        assert_eq!(
+            state.add_car_turning(Direction::East, Turning::Right),
+            Err(SpawnError::QueueFull(Direction::East, Turning::Right))
+        );
+
+        assert_eq!(state.stats.rejected(), 2);
+        assert_eq!(state.stats.delayed(), 2);
+        assert_eq!(state.queued_per_approach(), [0, 2, 0, 0]);
+        assert_eq!(state.car_count(), 4);
+        assert!(state.is_queued(4) && state.car(4).is_none());
+
+        // The queue empties as the lane clears
+        while state.is_queued(5) && state.tick < 600 {
+            state.update();
+        }
+        assert!(state.car(5).is_some());
+        assert_eq!(state.queued_per_approach(), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_approach_at() {
+        let at = |x: f32, y: f32| Path::approach_at(x * SECTOR_WIDTH, y * SECTOR_WIDTH);
+
+        assert_eq!(at(5.5, 0.5), Some((Direction::North, Turning::Left)));
+        assert_eq!(at(7.5, 11.5), Some((Direction::South, Turning::Straight)));
+        assert_eq!(at(0.5, 8.5), Some((Direction::West, Turning::Right)));
+        assert_eq!(at(9.5, 5.5), Some((Direction::East, Turning::Left)));
+        // Inside the intersection and outside the roads
+        assert_eq!(at(5.5, 5.5), None);
+        assert_eq!(at(0.5, 0.5), None);
+    }
+
+    #[test]
+    fn test_loops() {
+        let loops = Path::loops();
+        assert!(!loops.is_empty());
+        assert!(loops.iter().all(|sectors| sectors.len() >= 4));
+
+        // Every loop is closed, cars drive from each sector on to the next and from the last
+        // back to the first, and goes through every sector once
+        let driven = |from: &path::Sector, to: &path::Sector| {
+            Path::all()
+                .iter()
+                .any(|path| path.sectors.windows(2).any(|pair| pair == [*from, *to]))
+        };
+        for sectors in loops {
+            for (i, sector) in sectors.iter().enumerate() {
+                assert!(driven(sector, &sectors[(i + 1) % sectors.len()]));
+                assert!(!sectors[i + 1..].contains(sector));
+            }
+        }
+
+        // Left turns run around the middle
+        let middle: Vec<(usize, usize)> = loops
+            .iter()
+            .find(|sectors| sectors.len() == 4)
+            .unwrap()
+            .iter()
+            .map(|sector| (sector.get_x(), sector.get_y()))
+            .collect();
+        assert_eq!(middle, vec![(5, 5), (5, 6), (6, 6), (6, 5)]);
+
+        let left = Path::get(&Direction::West, &Turning::Left);
+        let right = Path::get(&Direction::West, &Turning::Right);
+        assert!(!left.loops_from(path::APPROACH_LENGTH).is_empty());
+        assert!(left.loops_from(left.sectors.len() - 2).is_empty());
+        assert!(right.loops_from(0).is_empty());
+    }
+
     #[tokio::test]
     async fn test_simulation() {
         let state = common::setup().await;
@@ -60,10 +194,132 @@ mod test_state {
 
         assert_eq!(state.stats.collisions(), 0);
     }
+
+    #[test]
+    fn test_braking_for() {
+        let mut state = State::default();
+        let mut leader = Car::new(Direction::North, Turning::Straight, 0);
+        leader.index = 4;
+        leader.y = 4.0 * SECTOR_WIDTH;
+        let mut follower = Car::new(Direction::North, Turning::Straight, 1);
+        follower.index = 3;
+        follower.y = 3.0 * SECTOR_WIDTH;
+        state.roads[0].add_car(leader);
+        state.roads[0].add_car(follower);
+
+        state.update();
+
+        let cars = &state.roads[0].cars[1];
+        assert_eq!(cars[0].braking_for, None);
+        assert_eq!(cars[1].braking_for, Some(0));
+        assert_eq!(cars[1].brake_reason, Some(BrakeReason::ForwardScan));
+
+        assert_eq!(state.car(1).map(|c| c.id), Some(1));
+        assert_eq!(
+            state.car_at(4.5 * SECTOR_WIDTH, 3.5 * SECTOR_WIDTH),
+            state.car(1)
+        );
+        assert_eq!(state.car_at(0.0, 0.0), None);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_update() {
+        common::assert_parallel(State::default(), 2000);
+    }
 }
+
+mod test_clock {
+    use smart_road::config::{DT, FPS, MAX_TICKS_PER_FRAME};
+    use smart_road::traffic::Clock;
+
+    #[test]
+    fn fixed_timestep() {
+        let mut clock = Clock::new();
+        assert_eq!(clock.advance(1.0), FPS as u32);
+
+        // Partial ticks are carried over to the next frame
+        assert_eq!(clock.advance(DT * 0.6), 0);
+        assert_eq!(clock.advance(DT * 0.6), 1);
+
+        let ticks: u32 = (0..30).map(|_| clock.advance(1.0 / 30.0)).sum();
+        assert_eq!(ticks, FPS as u32);
+    }
+
+    #[test]
+    fn time_scale() {
+        let mut clock = Clock::new();
+        clock.time_scale = 0.25;
+        assert_eq!(clock.advance(1.0), FPS as u32 / 4);
+
+        clock.time_scale = 16.0;
+        assert_eq!(clock.advance(1.0), MAX_TICKS_PER_FRAME);
+    }
+
+    #[test]
+    fn paused() {
+        let mut clock = Clock::new();
+        clock.toggle_pause();
+        assert_eq!(clock.advance(1.0), 0);
+
+        clock.step();
+        clock.step();
+        assert_eq!(clock.advance(1.0), 2);
+        assert_eq!(clock.advance(1.0), 0);
+
+        clock.toggle_pause();
+        assert_eq!(clock.advance(1.0), FPS as u32);
+    }
+
+    #[test]
+    fn time_scale_bounds() {
+        let mut clock = Clock::new();
+        for _ in 0..10 {
+            clock.speed_up();
+        }
+        assert_eq!(clock.time_scale, 16.0);
+
+        for _ in 0..10 {
+            clock.slow_down();
+        }
+        assert_eq!(clock.time_scale, 0.25);
+    }
+}
+
+mod test_camera {
+    use macroquad::math::vec2;
+    use smart_road::config::WORLD_SIZE;
+    use smart_road::render::Camera;
+
+    #[test]
+    fn world_fits_screen() {
+        let camera = Camera::new();
+        let screen = vec2(1600.0, 800.0);
+
+        // The world is centered and scaled to the shortest side of the screen
+        assert_eq!(camera.to_screen(vec2(0.0, 0.0), screen), vec2(400.0, 0.0));
+        assert_eq!(
+            camera.to_screen(vec2(WORLD_SIZE, WORLD_SIZE), screen),
+            vec2(1200.0, 800.0)
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut camera = Camera::new();
+        camera.zoom = 2.5;
+        camera.target = vec2(120.0, 700.0);
+        let screen = vec2(1024.0, 768.0);
+
+        let point = vec2(300.0, 450.0);
+        let world = camera.to_world(point, screen);
+        assert!((camera.to_screen(world, screen) - point).length() < 1e-3);
+    }
+}
+
 mod test_statistics {
     use macroquad::rand::gen_range;
-    use smart_road::traffic::Statistics;
+    use smart_road::traffic::{Direction, Statistics};
 
     #[test]
     fn max_vehicles() {
@@ -134,6 +390,31 @@ mod test_statistics {
         assert_eq!(stats.max_velocity(), n + 1.0);
     }
 
+    #[test]
+    fn throughput() {
+        let mut stats = Statistics::new();
+        stats.set_exit(10.0, 4.0);
+        stats.set_exit(50.0, 6.0);
+        stats.set_exit(80.0, 8.0);
+
+        assert_eq!(stats.finished(), 3);
+        assert_eq!(stats.mean_time(), 6.0);
+        assert_eq!(stats.throughput(80.0), 2);
+        assert_eq!(stats.throughput(200.0), 0);
+    }
+
+    #[test]
+    fn approach_wait() {
+        let mut stats = Statistics::new();
+        stats.set_wait(&Direction::North, 2.0);
+        stats.set_wait(&Direction::North, 4.0);
+        stats.set_wait(&Direction::West, 1.0);
+
+        assert_eq!(stats.approach_wait(&Direction::North), (3.0, 4.0));
+        assert_eq!(stats.approach_wait(&Direction::East), (0.0, 0.0));
+        assert_eq!(stats.max_wait(), 4.0);
+    }
+
     #[test]
     fn collisions() {
         let mut stats = Statistics::new();
@@ -145,3 +426,1345 @@ mod test_statistics {
         assert_eq!(stats.collisions(), n);
     }
 }
+mod test_spatial {
+    use smart_road::config::{SCAN_DISTANCE, SECTOR_WIDTH};
+    use smart_road::traffic::*;
+
+    fn car_at(id: usize, x: f32, y: f32) -> Car {
+        let mut car = Car::new(Direction::North, Turning::Straight, id);
+        car.index = 1;
+        car.x = x;
+        car.y = y;
+        car
+    }
+
+    #[test]
+    fn nearby_matches_brute_force() {
+        let cars: Vec<Car> = (0..12)
+            .flat_map(|i| (0..12).map(move |j| (i, j)))
+            .enumerate()
+            .map(|(id, (i, j))| car_at(id, i as f32 * SECTOR_WIDTH, j as f32 * SECTOR_WIDTH))
+            .collect();
+        let cars: Vec<CarView> = cars.iter().map(Car::view).collect();
+        let index = SpatialIndex::new(cars.clone());
+
+        for car in &cars {
+            let mut expected: Vec<usize> = cars
+                .iter()
+                .filter(|c| car.calc_dist(c) < SCAN_DISTANCE)
+                .map(|c| c.id)
+                .collect();
+            let mut found: Vec<usize> = index
+                .nearby(car.center_car(), SCAN_DISTANCE)
+                .filter(|c| car.calc_dist(c) < SCAN_DISTANCE)
+                .map(|c| c.id)
+                .collect();
+            expected.sort();
+            found.sort();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn in_sector() {
+        let car = car_at(0, 4.0 * SECTOR_WIDTH, 0.0);
+        let index = SpatialIndex::new([car.view()]);
+
+        assert_eq!(index.in_sector(&car.sector(0)).count(), 1);
+        assert_eq!(index.in_sector(&car.sector(1)).count(), 0);
+        assert_eq!(index.in_middle().count(), 0);
+    }
+}
+mod test_replay {
+    use smart_road::traffic::replay::*;
+    use smart_road::traffic::*;
+
+    #[test]
+    fn record_and_read() {
+        let mut state = State::default();
+        state.start_recording();
+        state
+            .add_car_turning(Direction::North, Turning::Left)
+            .unwrap();
+        state
+            .add_car_turning(Direction::East, Turning::Straight)
+            .unwrap();
+        for _ in 0..10 {
+            state.update();
+        }
+
+        let mut recorder = Recorder::new(Vec::new());
+        recorder.write(&mut state).unwrap();
+        assert_eq!(state.drain_events().count(), 0);
+
+        let events = read_events(recorder.into_inner().as_slice()).unwrap();
+        assert_eq!(events.len(), 12);
+        assert!(matches!(
+            &events[0],
+            Event::Spawn(Spawn {
+                id: 0,
+                direction: Direction::North,
+                turning: Turning::Left,
+                ..
+            })
+        ));
+        let Event::Tick(frame) = &events[11] else {
+            panic!("expected a tick, got {:?}", events[11]);
+        };
+        assert_eq!(frame.tick, 10);
+        assert_eq!(frame.cars.len(), 2);
+        let car = state.car(1).unwrap();
+        assert_eq!(frame.cars[1], CarFrame::from(car));
+    }
+
+    fn frame(tick: u64, collisions: Vec<usize>, close_calls: Vec<usize>) -> Event {
+        Event::Tick(Frame {
+            tick,
+            cars: Vec::new(),
+            collisions,
+            close_calls,
+        })
+    }
+
+    #[test]
+    fn playback() {
+        let mut replay = Replay::new((1..=600).map(|tick| match tick {
+            100 => frame(tick, vec![3], vec![]),
+            300 => frame(tick, vec![], vec![1, 2]),
+            _ => frame(tick, vec![], vec![]),
+        }));
+        assert!((replay.duration() - 10.0).abs() < 1e-3);
+
+        replay.seek(2.0);
+        assert_eq!(replay.frame().map(|f| f.tick), Some(120));
+        replay.advance(30);
+        assert_eq!(replay.frame().map(|f| f.tick), Some(150));
+
+        assert!(replay.next_incident());
+        assert_eq!(replay.frame().map(|f| f.tick), Some(300));
+        assert!(!replay.next_incident());
+
+        replay.reverse = true;
+        assert!(replay.next_incident());
+        assert_eq!(replay.frame().map(|f| f.tick), Some(100));
+        replay.advance(1000);
+        assert_eq!(replay.frame().map(|f| f.tick), Some(1));
+
+        replay.reverse = false;
+        replay.advance(1000);
+        assert_eq!(replay.frame().map(|f| f.tick), Some(600));
+        assert_eq!(replay.incidents().count(), 2);
+    }
+
+    #[test]
+    fn not_recording() {
+        let mut state = State::default();
+        state.add_car(Direction::South).unwrap();
+        state.update();
+        assert_eq!(state.drain_events().count(), 0);
+    }
+
+    #[test]
+    fn invalid_line() {
+        let replay =
+            "{\"Tick\":{\"tick\":1,\"cars\":[],\"collisions\":[],\"close_calls\":[]}}\nnot json\n";
+        match read_events(replay.as_bytes()) {
+            Err(ReplayError::Parse { line, .. }) => assert_eq!(line, 2),
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+}
+mod test_scenario {
+    use crate::common;
+    use smart_road::config::SECTOR_WIDTH;
+    use smart_road::traffic::scenario::*;
+    use smart_road::traffic::watchdog::Deadlock;
+    use smart_road::traffic::*;
+
+    #[test]
+    fn left_turns() {
+        common::load("left_turns.json").run().unwrap();
+    }
+
+    #[test]
+    fn crossing() {
+        common::load("crossing.json").run().unwrap();
+    }
+
+    // Conflict patterns at the intersection, each of which has to clear without collisions
+    // or deadlocks within its tick budget and delay bound
+    #[test]
+    fn opposing_left_turns() {
+        common::load("opposing_left_turns.json").run().unwrap();
+    }
+
+    #[test]
+    fn left_against_straight() {
+        common::load("left_against_straight.json").run().unwrap();
+    }
+
+    #[test]
+    fn four_left_turns() {
+        common::load("four_left_turns.json").run().unwrap();
+    }
+
+    #[test]
+    fn saturated() {
+        common::load("saturated.json").run().unwrap();
+    }
+
+    #[test]
+    fn platoons() {
+        let state = common::load("platoons.json").run().unwrap();
+        assert!(state.stats.mean_platoon_size() >= 2.0);
+        assert!(state.stats.mean_platoon_gap() > 0.0);
+    }
+
+    #[test]
+    fn saturated_platooning() {
+        let mut scenario = common::load("saturated.json");
+        scenario.platooning = true;
+        let state = scenario.run().unwrap();
+        assert!(state.stats.max_platoon_size() > 1);
+    }
+
+    #[test]
+    fn failures() {
+        let scenario = Scenario::parse(
+            r#"{
+                "ticks": 10,
+                "spawns": [{ "tick": 0, "direction": "North", "turning": "Right" }],
+                "expect": [
+                    "NoCollisions",
+                    "AllExit",
+                    { "ExitsBefore": { "car": 0, "tick": 5 } },
+                    { "ExitsBefore": { "car": 1, "tick": 5 } }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            scenario.run().err(),
+            Some(vec![
+                Failure::Remaining(1),
+                Failure::LateExit {
+                    car: 0,
+                    tick: 5,
+                    exited: None
+                },
+                Failure::NotSpawned(1),
+            ])
+        );
+    }
+
+    // A collision during a single tick already fails the scenario
+    #[test]
+    fn single_tick_collision() {
+        // Two cars on top of each other in the middle of the intersection
+        let mut state = State::new();
+        for id in 0..2 {
+            let mut car = Car::new(Direction::North, Turning::Straight, id);
+            car.index = 5;
+            (car.x, car.y) = (5.0 * SECTOR_WIDTH, 5.0 * SECTOR_WIDTH);
+            state.roads[0].add_car(car);
+        }
+        state.update();
+        assert_eq!(state.stats.collisions(), 0);
+        assert_eq!(state.stats.collision_events(), 2);
+
+        let scenario = Scenario::parse(r#"{ "ticks": 1, "expect": ["NoCollisions"] }"#).unwrap();
+        assert_eq!(
+            ScenarioRunner::new(scenario).check(&state),
+            vec![Failure::Collisions(2)]
+        );
+    }
+
+    // A deadlock the watchdog broke fails the scenario, even if every car got out
+    #[test]
+    fn deadlock_fails() {
+        let mut state = State::new();
+        state.stats.set_deadlock(Deadlock {
+            tick: 0,
+            cycle: vec![0, 1],
+            granted: 0,
+        });
+
+        let scenario = Scenario::parse(r#"{ "ticks": 1, "expect": ["NoDeadlocks"] }"#).unwrap();
+        assert_eq!(
+            ScenarioRunner::new(scenario).check(&state),
+            vec![Failure::Deadlocks(1)]
+        );
+    }
+
+    // The ticks a spawn waits for its lane count as delay
+    #[test]
+    fn delay_from_due_tick() {
+        let scenario = Scenario::parse(
+            r#"{
+                "ticks": 10,
+                "spawns": [{ "tick": 0, "direction": "North", "turning": "Right", "count": 2 }]
+            }"#,
+        )
+        .unwrap();
+        let mut state = State::new();
+        let mut runner = ScenarioRunner::new(scenario);
+        while !runner.is_finished(&state) {
+            runner.update(&mut state);
+        }
+        assert_eq!(state.car_count(), 1);
+        assert_eq!(
+            runner.delays(&state).collect::<Vec<_>>(),
+            [(0, 10), (1, 10)]
+        );
+    }
+}
+mod test_fairness {
+    use smart_road::config::SECTOR_WIDTH;
+    use smart_road::traffic::*;
+
+    // A car going straight, in the top left corner of the sector `index` of its path
+    fn car_at(direction: Direction, index: usize, id: usize) -> Car {
+        let mut car = Car::new(direction, Turning::Straight, id);
+        let sector = car.path.sectors[index];
+        car.index = index;
+        car.x = sector.get_x() as f32 * SECTOR_WIDTH;
+        car.y = sector.get_y() as f32 * SECTOR_WIDTH;
+        car
+    }
+
+    #[test]
+    fn give_way_to_priority() {
+        let mut state = State::new();
+        let mut waiting = car_at(Direction::North, 1, 0);
+        waiting.waited = state.max_wait;
+        waiting.priority = true;
+        state.roads[0].add_car(waiting);
+        // Halfway into the last sector before the stop line, moving left
+        let mut east = car_at(Direction::East, APPROACH_LENGTH - 1, 1);
+        east.x += SECTOR_WIDTH / 2.0;
+        state.roads[1].add_car(east);
+
+        state.update();
+
+        let east = state.car(1).unwrap();
+        assert_eq!(east.brake_reason, Some(BrakeReason::GiveWay));
+        assert_eq!(east.braking_for, Some(0));
+        assert_eq!(east.vel, 0.0);
+        assert!(state.car(0).unwrap().priority);
+    }
+
+    #[test]
+    fn priority_after_max_wait() {
+        let mut state = State::new();
+        state.max_wait = 5;
+        let mut car = car_at(Direction::North, 1, 0);
+        car.stop();
+        state.roads[0].add_car(car.clone());
+        // A car in the sector in front keeps it standing still
+        let mut blocker = car_at(Direction::North, 2, 1);
+        blocker.stop();
+        state.roads[0].add_car(blocker);
+
+        for _ in 0..4 {
+            state.update();
+        }
+        assert!(!state.car(0).unwrap().priority);
+        state.update();
+        let car = state.car(0).unwrap();
+        assert_eq!(car.waited, 5);
+        assert!(car.priority);
+        assert_eq!(
+            state.waiting_per_approach()[0],
+            5.0 * smart_road::config::DT
+        );
+    }
+}
+mod test_watchdog {
+    use smart_road::traffic::car::BrakeReason;
+    use smart_road::traffic::road::Road;
+    use smart_road::traffic::watchdog::{Watchdog, DEADLOCK_TICKS, LOOP_SLACK};
+    use smart_road::traffic::*;
+
+    #[test]
+    fn four_left_turns() {
+        let mut state = State::new();
+        for direction in Direction::ALL {
+            state.add_car_turning(direction, Turning::Left).unwrap();
+        }
+        let mut held = Vec::new();
+        while state.car_count() > 0 && state.tick < 2000 {
+            state.update();
+            let left = state.roads.iter().flat_map(|road| road.cars[0].iter());
+            let middle = left
+                .clone()
+                .filter(|car| (4..7).contains(&car.index))
+                .count();
+            assert!(middle <= 4 - LOOP_SLACK, "{middle} cars in the middle");
+            held.extend(
+                left.filter(|car| car.holding_for.is_some())
+                    .map(|car| car.id),
+            );
+        }
+
+        assert_eq!(state.car_count(), 0);
+        assert_eq!(state.stats.collision_events(), 0);
+        assert!(state.stats.deadlocks().is_empty());
+        held.sort();
+        held.dedup();
+        assert_eq!(
+            held.len(),
+            LOOP_SLACK,
+            "expected {LOOP_SLACK} cars to hold, got {held:?}"
+        );
+    }
+
+    // Cars going straight from `directions`, standing in the intersection, each braking for the
+    // next and the last for the first
+    fn cycle(directions: &[Direction]) -> [Road; 4] {
+        let mut roads = Direction::ALL.map(Road::new);
+        for (id, &direction) in directions.iter().enumerate() {
+            let mut car = Car::new(direction, Turning::Straight, id);
+            car.index = 4;
+            car.braking_for = Some((id + 1) % directions.len());
+            roads[direction.index()].add_car(car);
+        }
+        roads
+    }
+
+    #[test]
+    fn right_of_way() {
+        let mut roads = cycle(&[Direction::North, Direction::East]);
+        let mut watchdog = Watchdog::new();
+        for tick in 0..DEADLOCK_TICKS {
+            assert!(watchdog.check(&mut roads, tick).is_empty());
+        }
+        let [deadlock] = &watchdog.check(&mut roads, DEADLOCK_TICKS)[..] else {
+            panic!("expected one deadlock");
+        };
+        assert_eq!(deadlock.cycle, vec![0, 1]);
+        assert_eq!(deadlock.granted, 0);
+
+        let north = &roads[0].cars[1][0];
+        let east = &roads[1].cars[1][0];
+        assert!(north.right_of_way && north.holding_for.is_none());
+        assert!(!east.right_of_way && east.holding_for == Some(0));
+
+        // The grant holds until the car has left the intersection, without counting again
+        roads[0].cars[1][0].braking_for = None;
+        for tick in DEADLOCK_TICKS + 1..DEADLOCK_TICKS * 3 {
+            roads[0].cars[1][0].y += 0.1;
+            assert!(watchdog.check(&mut roads, tick).is_empty());
+        }
+        assert_eq!(roads[1].cars[1][0].holding_for, Some(0));
+        roads[0].cars[1][0].index = 9;
+        watchdog.check(&mut roads, DEADLOCK_TICKS * 3);
+        assert!(!roads[0].cars[1][0].right_of_way);
+        assert_eq!(roads[1].cars[1][0].holding_for, None);
+    }
+
+    #[test]
+    fn stuck_right_of_way_moves_on() {
+        let mut roads = cycle(&[Direction::North, Direction::East]);
+        // Off the path of the other car, so both are clear to go
+        roads[0].cars[1][0].index = 6;
+        let mut watchdog = Watchdog::new();
+        for tick in 0..DEADLOCK_TICKS * 2 {
+            watchdog.check(&mut roads, tick);
+        }
+        assert!(roads[0].cars[1][0].right_of_way);
+
+        // The car with right of way has not moved since it got it
+        let [deadlock] = &watchdog.check(&mut roads, DEADLOCK_TICKS * 2)[..] else {
+            panic!("expected one deadlock");
+        };
+        assert_eq!(deadlock.granted, 1);
+        assert!(!roads[0].cars[1][0].right_of_way);
+        assert_eq!(roads[0].cars[1][0].holding_for, Some(1));
+        assert!(roads[1].cars[1][0].right_of_way);
+    }
+
+    #[test]
+    fn holding_cars_stop() {
+        let mut state = State::new();
+        let id = state
+            .add_car_turning(Direction::North, Turning::Straight)
+            .unwrap();
+        for _ in 0..30 {
+            state.update();
+        }
+        let car = &mut state.roads[0].cars[1][0];
+        car.holding_for = Some(99);
+        let (x, y) = (car.x, car.y);
+        state.update();
+
+        let car = &state.roads[0].cars[1][0];
+        assert_eq!(car.id, id);
+        assert_eq!((car.x, car.y), (x, y));
+        assert_eq!(car.brake_reason, Some(BrakeReason::RightOfWay));
+        assert_eq!(car.braking_for, Some(99));
+    }
+
+    #[test]
+    fn waiting_is_not_a_deadlock() {
+        let mut state = State::new();
+        state
+            .add_car_turning(Direction::North, Turning::Straight)
+            .unwrap();
+        state
+            .add_car_turning(Direction::East, Turning::Straight)
+            .unwrap();
+        for _ in 0..DEADLOCK_TICKS * 10 {
+            state.update();
+        }
+        assert!(state.stats.deadlocks().is_empty());
+    }
+}
+
+mod test_demand {
+    use smart_road::config::FPS;
+    use smart_road::traffic::demand::*;
+    use smart_road::traffic::*;
+
+    // Arrivals of `demand` over `seconds` of simulated time, with the tick they arrived at
+    fn arrivals(demand: Demand, seconds: u64) -> Vec<(u64, Direction, Turning)> {
+        let mut generator = DemandGenerator::new(demand);
+        (0..seconds * FPS)
+            .flat_map(|tick| {
+                generator
+                    .arrivals(tick)
+                    .into_iter()
+                    .map(move |(direction, turning)| (tick, direction, turning))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn poisson_rate() {
+        let mut demand = Demand::uniform(1.0);
+        demand.approaches[Direction::West.index()].rate = 0.0;
+        let arrivals = arrivals(demand, 1000);
+
+        for direction in [Direction::North, Direction::East, Direction::South] {
+            let count = arrivals.iter().filter(|(_, d, _)| *d == direction).count();
+            // The standard deviation of 1000 expected arrivals is about 32
+            assert!((900..1100).contains(&count), "{direction:?}: {count}");
+        }
+        assert!(arrivals.iter().all(|(_, d, _)| *d != Direction::West));
+    }
+
+    #[test]
+    fn turning_ratios() {
+        let mut demand = Demand::uniform(1.0);
+        demand.approaches[Direction::North.index()].turning = [1.0, 0.0, 3.0];
+        let north: Vec<Turning> = arrivals(demand, 1000)
+            .into_iter()
+            .filter(|(_, d, _)| *d == Direction::North)
+            .map(|(_, _, turning)| turning)
+            .collect();
+
+        let left = north.iter().filter(|&&t| t == Turning::Left).count() as f32;
+        assert!(!north.contains(&Turning::Straight));
+        assert!((left / north.len() as f32 - 0.25).abs() < 0.05);
+    }
+
+    #[test]
+    fn platoons() {
+        let mut demand = Demand::uniform(0.0);
+        demand.approaches[Direction::East.index()] = Approach {
+            rate: 0.1,
+            turning: [1.0; 3],
+            platoon: Some(Platoon {
+                min: 3,
+                max: 3,
+                headway: 1.0,
+            }),
+        };
+        let ticks: Vec<u64> = arrivals(demand, 1000)
+            .into_iter()
+            .map(|(tick, ..)| tick)
+            .collect();
+
+        assert!(ticks.len() > 100);
+        for platoon in ticks.chunks_exact(3) {
+            for gap in platoon.windows(2).map(|w| w[1] - w[0]) {
+                assert!(
+                    gap.abs_diff(FPS) <= 1,
+                    "{gap} ticks between cars of a platoon"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed() {
+        let mut demand = Demand::uniform(0.5);
+        assert_eq!(arrivals(demand.clone(), 100), arrivals(demand.clone(), 100));
+
+        demand.seed = 1;
+        assert_ne!(arrivals(Demand::uniform(0.5), 100), arrivals(demand, 100));
+    }
+
+    #[test]
+    fn state_spawns_arrivals() {
+        let mut state = State::new();
+        state.demand = DemandGenerator::new(Demand::uniform(0.5));
+        for _ in 0..FPS * 10 {
+            state.update();
+        }
+        assert_eq!(state.total_cars, 0);
+
+        state.random = true;
+        for _ in 0..FPS * 60 {
+            state.update();
+        }
+        // Every arrival since `random` was turned on either spawned or was rejected
+        let expected = arrivals(Demand::uniform(0.5), 70)
+            .iter()
+            .filter(|(tick, ..)| *tick >= FPS * 10)
+            .count();
+        assert!(state.total_cars > 0);
+        assert_eq!(state.total_cars + state.stats.rejected(), expected);
+    }
+
+    #[test]
+    fn parse() {
+        let demand = Demand::parse(
+            r#"{
+                "seed": 7,
+                "approaches": [
+                    { "rate": 0.5, "turning": [1.0, 2.0, 1.0] },
+                    { "rate": 0.2 },
+                    { "rate": 0.1, "platoon": { "min": 3, "max": 6, "headway": 1.0 } },
+                    { "rate": 0.0 }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(demand.seed, 7);
+        assert_eq!(demand.approaches[1].turning, [1.0; 3]);
+        assert_eq!(demand.approaches[2].platoon.unwrap().max, 6);
+        assert!(Demand::parse(r#"{ "approaches": [] }"#).is_err());
+    }
+
+    // A platoon that can not arrive is rejected instead of failing when it is due
+    #[test]
+    fn invalid_platoon() {
+        let parse = |platoon: &str| {
+            Demand::parse(&format!(
+                r#"{{ "approaches": [{{ "rate": 0.1 }}, {{ "rate": 0.1, "platoon": {platoon} }},
+                    {{ "rate": 0.1 }}, {{ "rate": 0.1 }}] }}"#
+            ))
+        };
+        assert!(parse(r#"{ "min": 0, "max": 1, "headway": 0.0 }"#).is_ok());
+        for platoon in [
+            r#"{ "min": 0, "max": 0, "headway": 1.0 }"#,
+            r#"{ "min": 5, "max": 3, "headway": 1.0 }"#,
+            r#"{ "min": 1, "max": 3, "headway": -1.0 }"#,
+        ] {
+            assert!(matches!(
+                parse(platoon),
+                Err(DemandError::Platoon { approach: 1, .. })
+            ));
+        }
+    }
+}
+
+mod test_profile {
+    use smart_road::config::FPS;
+    use smart_road::traffic::demand::*;
+    use smart_road::traffic::*;
+
+    const CSV: &str = "time,north,south\n0,0,360\n100,3600,360\n";
+
+    #[test]
+    fn parse() {
+        let profile = Profile::parse(CSV).unwrap();
+        assert_eq!(profile.points.len(), 2);
+        assert_eq!(profile.points[1], (100.0, [3600.0, 0.0, 360.0, 0.0]));
+
+        let line = |csv: &str| match Profile::parse(csv) {
+            Err(DemandError::Csv { line, .. }) => line,
+            other => panic!("expected an invalid profile, got {other:?}"),
+        };
+        assert_eq!(line("time,up\n0,1\n"), 1);
+        assert_eq!(line("time,north\n0,1\n0,2\n"), 3);
+        assert_eq!(line("time,north\n0,1,2\n"), 2);
+        assert_eq!(line("time,north\n\n0,x\n"), 3);
+        assert_eq!(line("time,north\n"), 1);
+    }
+
+    #[test]
+    fn rate() {
+        let profile = Profile::parse(CSV).unwrap();
+        let north = Direction::North.index();
+        assert_eq!(profile.rate(north, -10.0), 0.0);
+        assert_eq!(profile.rate(north, 25.0), 900.0);
+        assert_eq!(profile.rate(north, 100.0), 3600.0);
+        assert_eq!(profile.rate(north, 1000.0), 3600.0);
+        assert_eq!(profile.rate(Direction::East.index(), 50.0), 0.0);
+    }
+
+    #[test]
+    fn arrivals_follow_profile() {
+        let mut demand = Demand::uniform(0.0);
+        demand.profile = Some(Profile::parse(CSV).unwrap());
+        let mut generator = DemandGenerator::new(demand);
+        let mut counts = [[0; 4]; 2];
+        for tick in 0..200 * FPS {
+            for (direction, _) in generator.arrivals(tick) {
+                counts[(tick >= 100 * FPS) as usize][direction.index()] += 1;
+            }
+        }
+
+        let [first, second] = counts;
+        let (north, south) = (Direction::North.index(), Direction::South.index());
+        // North ramps up from 0 to 1 car per second, then stays there
+        assert!((30..70).contains(&first[north]), "{first:?}");
+        assert!((80..120).contains(&second[north]), "{second:?}");
+        // South stays at 0.1 cars per second
+        assert!(first[south] + second[south] < 40, "{first:?} {second:?}");
+        assert_eq!(first[Direction::East.index()], 0);
+    }
+
+    #[test]
+    fn rush_hour() {
+        let mut state = State::new();
+        let mut demand = Demand::uniform(0.0);
+        demand.profile = Some(Profile::load("profiles/rush_hour.csv").unwrap());
+        state.demand = DemandGenerator::new(demand);
+        state.random = true;
+        while state.tick < 1200 * FPS {
+            state.update();
+        }
+
+        let samples = state.stats.samples();
+        assert_eq!(samples.len(), 20);
+        assert!(samples.windows(2).all(|w| w[0].time < w[1].time));
+        let exits = |range: std::ops::Range<usize>, directions: [Direction; 2]| -> usize {
+            samples[range]
+                .iter()
+                .flat_map(|sample| directions.map(|d| sample.exits[d.index()]))
+                .sum()
+        };
+        let corridor = [Direction::North, Direction::South];
+        let cross = [Direction::East, Direction::West];
+        // The morning peak is on the north/south corridor, the evening peak on east/west
+        assert!(exits(0..10, corridor) > exits(0..10, cross));
+        assert!(exits(10..20, cross) > exits(10..20, corridor));
+        assert_eq!(state.stats.collision_events(), 0);
+    }
+
+    // A car alone in the world takes exactly as long as driving through freely
+    #[test]
+    fn no_delay_alone() {
+        let mut state = State::new();
+        for direction in Direction::ALL {
+            for turning in Turning::ALL {
+                state.add_car_turning(direction, turning).unwrap();
+                while state.car_count() > 0 {
+                    state.update();
+                }
+            }
+        }
+        while state.stats.samples().is_empty() {
+            state.update();
+        }
+
+        let [sample] = state.stats.samples() else {
+            panic!("expected one sample, got {:?}", state.stats.samples());
+        };
+        assert_eq!(sample.exits, [3; 4]);
+        assert_eq!(sample.delay, [0.0; 4]);
+        assert!(Car::free_flow_time(Direction::North, Turning::Left) > 0.0);
+    }
+}
+
+mod test_od {
+    use smart_road::config::FPS;
+    use smart_road::traffic::demand::*;
+    use smart_road::traffic::*;
+
+    const CSV: &str = "origin,north,east,south,west
+north,0,360,1080,0
+east,0,0,0,720
+south,0,0,0,0
+";
+
+    #[test]
+    fn exits_match_paths() {
+        for direction in Direction::ALL {
+            for turning in Turning::ALL {
+                let exit = Path::get(&direction, &turning).sectors.last().unwrap();
+                let side = match (exit.get_x(), exit.get_y()) {
+                    (_, 0) => Direction::North,
+                    (11, _) => Direction::East,
+                    (_, 11) => Direction::South,
+                    _ => Direction::West,
+                };
+                assert_eq!(direction.exit(&turning), side, "{direction:?} {turning:?}");
+                assert_eq!(direction.turning_to(side), Some(turning));
+            }
+            assert_eq!(direction.turning_to(direction), None);
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let od = OdMatrix::parse(CSV).unwrap();
+        assert_eq!(
+            od.counts[Direction::North.index()],
+            [0.0, 360.0, 1080.0, 0.0]
+        );
+        assert_eq!(od.counts[Direction::West.index()], [0.0; 4]);
+        // North to east is a left turn, north to south straight ahead
+        assert_eq!(od.turning(Direction::North), [360.0, 1080.0, 0.0]);
+        assert_eq!(od.turning(Direction::East), [0.0, 720.0, 0.0]);
+
+        let line = |csv: &str| match OdMatrix::parse(csv) {
+            Err(DemandError::Csv { line, .. }) => line,
+            other => panic!("expected an invalid OD matrix, got {other:?}"),
+        };
+        assert_eq!(line("origin,north\nnorth,10\n"), 2);
+        assert_eq!(line("origin,north,east\nup,0,1\n"), 2);
+        assert_eq!(line("origin,north,east\nsouth,1\n"), 2);
+        assert!(OdMatrix::load("profiles/junction_od.csv").is_ok());
+    }
+
+    #[test]
+    fn arrivals_match_counts() {
+        let od = OdMatrix::parse(CSV).unwrap();
+        let mut generator = DemandGenerator::new(Demand::from_od(&od));
+        let mut counts = [[0.0; 4]; 4];
+        for tick in 0..3600 * FPS {
+            for (origin, turning) in generator.arrivals(tick) {
+                counts[origin.index()][origin.exit(&turning).index()] += 1.0;
+            }
+        }
+
+        for (origin, destination) in [(0, 1), (0, 2), (1, 3)] {
+            let expected: f32 = od.counts[origin][destination];
+            let count = counts[origin][destination];
+            // Within four standard deviations of a Poisson count
+            assert!(
+                (count - expected).abs() < 4.0 * expected.sqrt(),
+                "{origin} to {destination}: {count}, expected {expected}"
+            );
+        }
+        for (expected, count) in od.counts.iter().flatten().zip(counts.iter().flatten()) {
+            if *expected == 0.0 {
+                assert_eq!(*count, 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn add_car_follows_turning() {
+        let od = OdMatrix::parse(CSV).unwrap();
+        let mut state = State::new();
+        state.demand = DemandGenerator::new(Demand::from_od(&od));
+
+        let id = state.add_car(Direction::East).unwrap();
+        assert_eq!(state.car(id).unwrap().turning, Turning::Straight);
+        // Only the straight lane has traffic from the east, the other lanes are still used
+        // when it is busy
+        let id = state.add_car(Direction::East).unwrap();
+        assert_ne!(state.car(id).unwrap().turning, Turning::Straight);
+    }
+}
+
+mod test_platoon {
+    use crate::common;
+    use smart_road::config::{MAX_PLATOON_SIZE, PLATOON_SPACING};
+    use smart_road::traffic::perception::Perception;
+    use smart_road::traffic::scenario::ScenarioRunner;
+    use smart_road::traffic::*;
+
+    fn variance(values: &[f32]) -> f32 {
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32
+    }
+
+    #[test]
+    fn forms_platoons() {
+        let mut state = State::new();
+        state.platooning = true;
+        let ids = common::spawn_line(&mut state, 3);
+
+        let [leader, second, third] = ids[..] else {
+            unreachable!()
+        };
+        assert_eq!(state.car(leader).unwrap().platoon, Some(leader));
+        assert_eq!(state.car(leader).unwrap().ahead, None);
+        assert_eq!(state.car(second).unwrap().platoon, Some(leader));
+        assert_eq!(state.car(second).unwrap().ahead, Some(leader));
+        assert_eq!(state.car(third).unwrap().ahead, Some(second));
+        assert_eq!(state.platoon_count(), 1);
+    }
+
+    #[test]
+    fn keeps_distance() {
+        let mut state = State::new();
+        state.platooning = true;
+        let ids = common::spawn_line(&mut state, 8);
+        while state.car_count() > 0 && state.tick < 3000 {
+            state.update();
+            for car in state.cars().filter(|car| car.vel > 0.0) {
+                let Some(ahead) = car.ahead.and_then(|id| state.car(id)) else {
+                    continue;
+                };
+                // Moving cars of a platoon never close in further than standing ones
+                assert!(car.calc_dist(&ahead.view()) > PLATOON_SPACING * 0.9);
+            }
+        }
+
+        assert_eq!(state.car_count(), 0);
+        assert_eq!(state.stats.close_call_events(), 0);
+        assert_eq!(state.stats.max_platoon_size(), MAX_PLATOON_SIZE);
+        assert!(ids.len() > MAX_PLATOON_SIZE);
+    }
+
+    // Cars matching the car in front of them instead of braking on their own keep the velocity
+    // of consecutive cars on the approach, and the gap between them, from swinging back and forth
+    #[test]
+    fn smooths_accordion() {
+        let accordion = |platooning| {
+            let mut scenario = common::load("platoons.json");
+            scenario.platooning = platooning;
+            let mut state = State::new();
+            state.platooning = platooning;
+            let mut runner = ScenarioRunner::new(scenario);
+            let (mut differences, mut gaps) = (Vec::new(), Vec::new());
+            while !runner.is_finished(&state) {
+                runner.update(&mut state);
+                for lane in state.roads.iter().flat_map(|road| road.cars.iter()) {
+                    for pair in lane.windows(2) {
+                        let (ahead, car) = (&pair[0], &pair[1]);
+                        let crossing =
+                            ahead.index < ahead.path.sectors.len() - path::APPROACH_LENGTH;
+                        if car.index < path::APPROACH_LENGTH && crossing {
+                            differences.push(ahead.vel - car.vel);
+                            gaps.push(car.calc_dist(&ahead.view()));
+                        }
+                    }
+                }
+            }
+            (variance(&differences), variance(&gaps))
+        };
+        let (velocity, gap) = accordion(false);
+        let (platoon_velocity, platoon_gap) = accordion(true);
+        assert!(
+            platoon_velocity < velocity,
+            "{platoon_velocity} >= {velocity}"
+        );
+        assert!(platoon_gap < gap, "{platoon_gap} >= {gap}");
+    }
+
+    // A car whose platoon has entered the intersection crosses with it instead of holding at
+    // the stop line for a car with priority
+    #[test]
+    fn crosses_as_a_unit() {
+        let mut state = State::new();
+        state.platooning = true;
+        let [leader, follower] = common::spawn_line(&mut state, 2)[..] else {
+            unreachable!()
+        };
+        let mut car = state.car(follower).unwrap().clone();
+        assert_eq!(car.ahead, Some(leader));
+        car.index = path::APPROACH_LENGTH - 1;
+        let mut priority = Car::new(Direction::East, Turning::Straight, 99);
+        priority.priority = true;
+
+        // Whether the car is granted to cross, and whether it holds, with the leader at `index`
+        let crosses = |index: usize, vel: f32| {
+            let mut views: Vec<CarView> = state.cars().map(Car::view).collect();
+            for view in views.iter_mut().filter(|view| view.id == leader) {
+                (view.index, view.vel) = (index, vel);
+            }
+            views.push(priority.view());
+            let cars = SpatialIndex::new(views);
+            let perception = Perception::default();
+            let seen = perception.perceive(&car, &cars, state.tick);
+
+            let mut car = car.clone();
+            car.brake_reason = None;
+            car.give_way(&seen);
+            (
+                car.is_granted(&seen),
+                car.brake_reason != Some(BrakeReason::GiveWay),
+            )
+        };
+        assert_eq!(crosses(path::APPROACH_LENGTH, 0.5), (true, true));
+        assert_eq!(crosses(path::APPROACH_LENGTH, 0.0), (false, false));
+        assert_eq!(crosses(path::APPROACH_LENGTH - 1, 0.5), (false, false));
+    }
+
+    #[test]
+    fn off_by_default() {
+        let mut state = State::new();
+        common::spawn_line(&mut state, 3);
+        assert!(state
+            .cars()
+            .all(|car| car.platoon.is_none() && car.ahead.is_none()));
+        assert_eq!(state.stats.max_platoon_size(), 0);
+    }
+}
+
+mod test_v2v {
+    use crate::common;
+    use smart_road::traffic::v2v::*;
+    use smart_road::traffic::*;
+
+    fn view() -> CarView {
+        let mut state = State::new();
+        let id = state
+            .add_car_turning(Direction::North, Turning::Straight)
+            .unwrap();
+        state.car(id).unwrap().view()
+    }
+
+    fn channel(latency: u64, jitter: u64, loss: f32) -> Channel {
+        Channel {
+            latency,
+            jitter,
+            loss,
+            ..Channel::default()
+        }
+    }
+
+    #[test]
+    fn perfect_channel() {
+        let mut bus = MessageBus::default();
+        let mut stats = Statistics::new();
+        bus.broadcast(5, [view()], &mut stats);
+        bus.deliver(5);
+
+        assert_eq!(
+            bus.received(),
+            &[Message {
+                sent: 5,
+                view: view()
+            }]
+        );
+        assert_eq!(stats.messages(), 1);
+        assert_eq!(stats.lost_messages(), 0);
+    }
+
+    #[test]
+    fn latency() {
+        let mut bus = MessageBus::new(channel(3, 0, 0.0));
+        let mut stats = Statistics::new();
+        bus.broadcast(0, [view()], &mut stats);
+        for tick in 0..3 {
+            bus.deliver(tick);
+            assert!(bus.received().is_empty());
+        }
+        bus.deliver(3);
+        assert_eq!(bus.received()[0].sent, 0);
+    }
+
+    #[test]
+    fn loss() {
+        let mut bus = MessageBus::new(channel(0, 0, 0.3));
+        let mut stats = Statistics::new();
+        for tick in 0..10000 {
+            bus.broadcast(tick, [view()], &mut stats);
+        }
+        assert_eq!(stats.messages(), 10000);
+        assert!((stats.message_loss() - 0.3).abs() < 0.02);
+    }
+
+    // Messages overtaking each other never replace a newer state with an older one
+    #[test]
+    fn keeps_newest() {
+        let mut bus = MessageBus::new(channel(0, 10, 0.0));
+        let mut stats = Statistics::new();
+        let mut latest = 0;
+        for tick in 0..200 {
+            bus.broadcast(tick, [view()], &mut stats);
+            bus.deliver(tick);
+            if let Some(message) = bus.received().first() {
+                assert!(message.sent >= latest);
+                latest = message.sent;
+            }
+        }
+        assert!(latest > 0);
+    }
+
+    #[test]
+    fn timeout() {
+        let mut bus = MessageBus::new(Channel {
+            timeout: 10,
+            ..Channel::default()
+        });
+        let mut stats = Statistics::new();
+        bus.broadcast(0, [view()], &mut stats);
+        bus.deliver(10);
+        assert_eq!(bus.received().len(), 1);
+        bus.deliver(11);
+        assert!(bus.received().is_empty());
+    }
+
+    // The timeout counts from the tick a message arrives, so a slow channel still delivers
+    #[test]
+    fn timeout_from_arrival() {
+        let mut bus = MessageBus::new(Channel {
+            latency: 20,
+            timeout: 10,
+            ..Channel::default()
+        });
+        let mut stats = Statistics::new();
+        bus.broadcast(0, [view()], &mut stats);
+        bus.deliver(20);
+        assert_eq!(bus.received().len(), 1);
+        bus.deliver(30);
+        assert_eq!(bus.received().len(), 1);
+        bus.deliver(31);
+        assert!(bus.received().is_empty());
+    }
+
+    #[test]
+    fn parse() {
+        let channel = Channel::parse(r#"{ "latency": 6, "loss": 0.1 }"#).unwrap();
+        assert_eq!(
+            channel,
+            Channel {
+                latency: 6,
+                loss: 0.1,
+                ..Channel::default()
+            }
+        );
+        assert!(matches!(
+            Channel::parse(r#"{ "loss": "high" }"#),
+            Err(ChannelError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn lossy_channel() {
+        let state = common::load("lossy_channel.json").run().unwrap();
+        assert!(state.stats.lost_messages() > 0);
+        assert!(state.stats.mean_message_age() > 0.0);
+    }
+
+    // Deciding on the state of 100 to 200 ms ago is enough to make cars collide when every lane
+    // is busy, while the perfect channel keeps them apart
+    #[test]
+    fn slow_channel_collides() {
+        let mut scenario = common::load("saturated.json");
+        scenario.expect.clear();
+        let perfect = scenario.run().unwrap();
+        scenario.channel = Channel {
+            seed: 7,
+            ..channel(6, 6, 0.0)
+        };
+        let slow = scenario.run().unwrap();
+        assert_eq!(perfect.stats.collision_events(), 0);
+        assert!(slow.stats.collision_events() > 0);
+    }
+
+    // The same seed loses and delays the same messages
+    #[test]
+    fn deterministic() {
+        let mut scenario = common::load("saturated.json");
+        scenario.channel = Channel {
+            seed: 3,
+            ..channel(6, 6, 0.1)
+        };
+        let state = common::assert_deterministic(scenario);
+        assert!(state.stats.messages() > 0);
+    }
+}
+
+mod test_perception {
+    use crate::common;
+    use smart_road::config::SECTOR_WIDTH;
+    use smart_road::traffic::perception::*;
+    use smart_road::traffic::*;
+
+    // Three cars going straight from the north, the first one furthest ahead
+    fn line() -> (State, [usize; 3]) {
+        let mut state = State::new();
+        let ids = common::spawn_line(&mut state, 3);
+        (state, [ids[0], ids[1], ids[2]])
+    }
+
+    fn perceived(state: &State, perception: Perception, id: usize) -> (Vec<usize>, usize) {
+        let cars = SpatialIndex::new(state.cars().map(Car::view));
+        let perceived = perception.perceive(state.car(id).unwrap(), &cars, state.tick);
+        let mut ids: Vec<usize> = perceived.cars().map(|c| c.id).collect();
+        ids.sort();
+        (ids, perceived.missed())
+    }
+
+    #[test]
+    fn perfect() {
+        let (state, [first, second, third]) = line();
+        assert!(Perception::default().is_perfect());
+        assert_eq!(
+            perceived(&state, Perception::default(), third),
+            (vec![first, second], 0)
+        );
+    }
+
+    #[test]
+    fn range() {
+        let (state, [first, second, third]) = line();
+        let distance = state
+            .car(third)
+            .unwrap()
+            .calc_dist(&state.car(first).unwrap().view());
+        let perception = Perception {
+            range: Some(distance - 1.0),
+            ..Perception::default()
+        };
+        assert_eq!(perceived(&state, perception, third).0, vec![second]);
+    }
+
+    #[test]
+    fn field_of_view() {
+        let (state, [first, second, third]) = line();
+        let perception = Perception {
+            fov: 180.0,
+            ..Perception::default()
+        };
+        assert_eq!(perceived(&state, perception, first).0, Vec::<usize>::new());
+        assert_eq!(perceived(&state, perception, third).0, vec![first, second]);
+    }
+
+    #[test]
+    fn occlusion() {
+        let (state, [first, second, third]) = line();
+        let perception = Perception {
+            occlusion: true,
+            ..Perception::default()
+        };
+        assert_eq!(perceived(&state, perception, third).0, vec![second]);
+        assert_eq!(perceived(&state, perception, second).0, vec![first, third]);
+    }
+
+    // The noise only depends on the seed, the tick and the two cars
+    #[test]
+    fn noise() {
+        let (state, [first, _, third]) = line();
+        let perception = Perception {
+            position_noise: 5.0,
+            velocity_noise: 0.1,
+            ..Perception::default()
+        };
+        let cars = SpatialIndex::new(state.cars().map(Car::view));
+        let car = state.car(third).unwrap();
+        let view = |tick| {
+            let perceived = perception.perceive(car, &cars, tick);
+            perceived.cars().find(|c| c.id == first).unwrap()
+        };
+        let actual = state.car(first).unwrap().view();
+
+        assert_eq!(view(1), view(1));
+        assert_ne!(view(1), view(2));
+        assert_ne!(view(1).x, actual.x);
+        assert!((view(1).x - actual.x).abs() < 5.0 * 5.0);
+        assert!(view(1).vel >= 0.0);
+    }
+
+    #[test]
+    fn parse() {
+        let perception = Perception::parse(r#"{ "range": 300.0, "occlusion": true }"#).unwrap();
+        assert_eq!(
+            perception,
+            Perception {
+                range: Some(300.0),
+                occlusion: true,
+                ..Perception::default()
+            }
+        );
+        assert!(!perception.is_perfect());
+        assert!(matches!(
+            Perception::parse(r#"{ "fov": "wide" }"#),
+            Err(PerceptionError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn limited_perception() {
+        let state = common::load("limited_perception.json").run().unwrap();
+        assert!(state.stats.missed() > 0);
+    }
+
+    // The checks on the sectors of the paths do not depend on what a car sees, so the
+    // saturated scenario takes a few units of noise, occlusion or a front-facing sensor without
+    // collisions, but not a sensor that misses the cars beside it or noise of a third of a sector
+    #[test]
+    fn saturated_collisions() {
+        let collisions = |perception: Perception| {
+            let mut scenario = common::load("saturated.json");
+            scenario.perception = Perception {
+                seed: 7,
+                ..perception
+            };
+            scenario.expect.clear();
+            scenario.run().unwrap().stats.collision_events()
+        };
+        let perfect = Perception::default();
+        assert_eq!(collisions(perfect), 0);
+        for perception in [
+            Perception {
+                position_noise: 3.0,
+                velocity_noise: 0.05,
+                ..perfect
+            },
+            Perception {
+                fov: 180.0,
+                ..perfect
+            },
+            Perception {
+                occlusion: true,
+                ..perfect
+            },
+        ] {
+            assert_eq!(collisions(perception), 0, "{perception:?}");
+        }
+        for perception in [
+            Perception {
+                position_noise: SECTOR_WIDTH / 3.0,
+                ..perfect
+            },
+            Perception {
+                range: Some(SECTOR_WIDTH * 1.5),
+                ..perfect
+            },
+            Perception {
+                fov: 60.0,
+                ..perfect
+            },
+        ] {
+            assert!(collisions(perception) > 0, "{perception:?}");
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let mut scenario = common::load("four_left_turns.json");
+        scenario.perception = Perception {
+            position_noise: 8.0,
+            velocity_noise: 0.1,
+            occlusion: true,
+            seed: 3,
+            ..Perception::default()
+        };
+        common::assert_deterministic(scenario);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel() {
+        let mut state = State::new();
+        state.perception = Perception {
+            position_noise: 8.0,
+            range: Some(300.0),
+            occlusion: true,
+            ..Perception::default()
+        };
+        common::assert_parallel(state, 1000);
+    }
+}